@@ -3,16 +3,20 @@ use std::net::TcpStream;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::{sleep, spawn, Thread};
 use std::time::Duration;
-use chess_logic_lib::board::{new_board, Board, Color, HEIGHT, WIDTH};
+use chess_logic_lib::board::{new_board, Board, Square};
 use chess_logic_lib::board::Color::{Black, White};
 use chess_logic_lib::communication_protocol::{JsonMsg, MsgType, MsgTypeServer, ServerMsg};
-use chess_logic_lib::moves::allowed_moves;
+use chess_logic_lib::variant::GameVariant;
+use chess_logic_lib::moves::{allowed_moves, from_san};
+use chess_logic_lib::search::{best_move, SearchLimits};
 use neural_network_lib::neural_network::NeuralNetwork;
 use tungstenite::{Message, WebSocket};
 use rand::random;
 use tungstenite::stream::MaybeTlsStream;
 
 const SERVER_ADDRESS: &str = "ws://127.0.0.1:9977";
+// thinking time when the neural network's move is illegal
+const ENGINE_TIME: Duration = Duration::from_secs(1);
 
 fn main() {
     let (tx, rx): (Sender<u8>, Receiver<u8>) = channel();
@@ -32,8 +36,13 @@ fn spawn_client(tx: Sender<u8>) {
         msg_type: MsgType::Create,
         room_id: 0,
         make_move: None,
+        promotion: None,
         possible_moves: None,
         room_name: Some("AI room".to_string()),
+        variant: GameVariant::Standard,
+        start_position: None,
+        engine: None,
+        time_control: None,
     };
     println!("msg: {:?}", msg);
 
@@ -57,7 +66,7 @@ fn spawn_client(tx: Sender<u8>) {
                 println!("server msg: {}", m);
                 match serde_json::from_str::<ServerMsg>(&m) {
                     Ok(msg) => match msg {
-                        ServerMsg::Board { current_board, last_move, in_check } => {
+                        ServerMsg::Board { last_move, last_move_san, .. } => {
                             if before_first_msg {
                                 let _ = tx.send(0);
                                 before_first_msg = false;
@@ -67,7 +76,12 @@ fn spawn_client(tx: Sender<u8>) {
                             }
                             else {
                                 if let Some((src, dst)) = last_move {
-                                    board.make_move(src, dst);
+                                    let promotion = last_move_san.and_then(|san| from_san(&board, &san).ok()).and_then(|m| m.promotion());
+                                    // a move our board does not accept means it went out of step with the server's, skip it rather than crash
+                                    if let Err(e) = board.try_create_move(src, dst, promotion).and_then(|m| board.try_make_move(m)) {
+                                        println!("cannot play {}{} on our board: {}", src, dst, e);
+                                        continue;
+                                    }
                                     network_input[move_i + 0] = src.0 as f32;
                                     network_input[move_i + 1] = src.1 as f32;
                                     network_input[move_i + 2] = dst.0 as f32;
@@ -78,21 +92,21 @@ fn spawn_client(tx: Sender<u8>) {
                                 let next_move = if move_i == 0 {
                                     let move_d4: bool = random();
                                     if move_d4 {
-                                        Some(((1, 3), (3, 3)))
+                                        Some((Square(1, 3), Square(3, 3)))
                                     }
                                     else {
-                                        Some(((1, 4), (3, 4)))
+                                        Some((Square(1, 4), Square(3, 4)))
                                     }
                                 }
                                 else {
                                     let next_move = neural_network_move(&network, &network_input);
                                     println!("neural network move: {:?}", next_move);
-                                    let next_move = if allowed_moves(&board, next_move.0.0, next_move.0.1, my_color).contains(&(next_move.1.0, next_move.1.1)){
+                                    let next_move = if allowed_moves(&board, next_move.0, my_color).iter().any(|m| m.to == next_move.1) {
                                         Some(next_move)
                                     }
                                     else {
-                                        println!("Illegal move, picking random");
-                                        pick_random_move(&board, my_color)
+                                        println!("Illegal move, asking the engine");
+                                        engine_move(&board)
                                     };
                                     next_move
                                 };
@@ -102,8 +116,13 @@ fn spawn_client(tx: Sender<u8>) {
                                         msg_type: MsgType::Move,
                                         room_id: my_room,
                                         make_move: next_move,
+                                        promotion: None,
                                         possible_moves: None,
                                         room_name: None,
+                                        variant: GameVariant::Standard,
+                                        start_position: None,
+                                        engine: None,
+                                        time_control: None,
                                     };
                                     socket.send(
                                         Message::text(serde_json::to_string(&new_move).unwrap())
@@ -115,6 +134,21 @@ fn spawn_client(tx: Sender<u8>) {
                         ServerMsg::Rooms { .. } => {}
                         ServerMsg::Disconnected => break,
                         ServerMsg::PlayersOnline { .. } => {}
+                        ServerMsg::Spectating { .. } => {}
+                        // the AI neither offers nor answers, a takeback granted by the server is followed on our board
+                        ServerMsg::DrawOffer { .. } | ServerMsg::DrawDeclined | ServerMsg::TakebackRequest { .. } | ServerMsg::TakebackDeclined => {}
+                        ServerMsg::Takeback { plies, .. } => {
+                            for _ in 0..plies {
+                                if board.unmake_move().is_some() && move_i >= 4 {
+                                    move_i -= 4;
+                                    network_input[move_i..move_i + 4].fill(0.0);
+                                }
+                            }
+                        }
+                        ServerMsg::RoomRefused { reason } => {
+                            println!("room refused: {}", reason);
+                            break;
+                        }
                     }
                     Err(_) => {
                         match serde_json::from_str::<chess_logic_lib::communication_protocol::JsonMsgServer>(&m) {
@@ -145,26 +179,18 @@ fn spawn_client(tx: Sender<u8>) {
     println!("Stopping");
 }
 
-fn neural_network_move(network: &NeuralNetwork, input: &[f32]) -> ((usize, usize), (usize, usize)) {
+fn neural_network_move(network: &NeuralNetwork, input: &[f32]) -> (Square, Square) {
     let res = network.process(input);
     println!("neural network; input: {:?}", input);
     println!("output: {:?}", res);
     let res: Vec<usize> = res.iter().map(|&x| x.round() as usize).collect();
-    ((res[0], res[1]), (res[2], res[3]))
+    (Square(res[0], res[1]), Square(res[2], res[3]))
 }
 
-fn pick_random_move(board: &Board, color: Color) -> Option<((usize, usize), (usize, usize))> {
-    let pieces: Vec<(usize, usize, (usize, usize))> = (0 .. WIDTH)
-        .flat_map(|r| (0 .. HEIGHT).map(move |c| (r, c) ))
-        .filter(|&(r, c)| board.squares[r][c].is_some_and(|x| x.color == color))
-        .flat_map(|(r, c)| allowed_moves(&board, r, c, color).iter().map(|&p| (r, c, p)).collect::<Vec<_>>() )
-        .collect();
-    if pieces.len() == 0 {
-        return None;
-    }
-    let i: usize = random::<u32>() as usize;
-    let (from_r, from_c, (to_r, to_c)) = pieces[i % pieces.len()];
-    Some(((from_r, from_c), (to_r, to_c)))
+fn engine_move(board: &Board) -> Option<(Square, Square)> {
+    let result = best_move(board, SearchLimits { time: Some(ENGINE_TIME), ..Default::default() });
+    println!("engine move: {:?}, score: {}", result.best_move.map(|m| m.to_string()), result.score);
+    result.best_move.map(|m| (m.from, m.to))
 }
 
 fn send_rematch(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>, room_id: u32) {
@@ -172,8 +198,13 @@ fn send_rematch(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>, room_id: u32)
         msg_type: MsgType::Rematch,
         room_id,
         make_move: None,
+        promotion: None,
         possible_moves: None,
         room_name: None,
+        variant: GameVariant::Standard,
+        start_position: None,
+        engine: None,
+        time_control: None,
     };
     let msg = serde_json::to_string(&msg).unwrap();
     socket.send(Message::Text(msg.into())).unwrap();
@@ -23,21 +23,45 @@ let rematchTextHtml = document.getElementById("rematch_text");
 let nameFieldHTML = document.getElementById("name_field");
 let disconnectHTML = document.getElementById("opponent_disconnected");
 let playerOnlineHTML = document.getElementById("player_online");
+let createRefusedHTML = document.getElementById("create_refused");
 let capturedPiecesUpHTML = document.getElementById("pieces_lost_up");
 let capturedPiecesDownHTML = document.getElementById("pieces_lost_down");
+let moveListHTML = document.getElementById("move_list");
+let promotionHTML = document.getElementById("promotion_piece");
+let variantHTML = document.getElementById("variant");
+let opponentHTML = document.getElementById("opponent");
+let timeControlHTML = document.getElementById("time_control");
+let clockUpHTML = document.getElementById("clock_up");
+let clockDownHTML = document.getElementById("clock_down");
+let offerHTML = document.getElementById("offer");
+let offerTextHTML = document.getElementById("offer_text");
+let offerAnswerHTML = document.getElementById("offer_answer");
+let rematchButtonHTML = document.getElementById("rematch_button");
+let playerControlsHTML = document.getElementById("player_controls");
+let disconnectedTextHTML = document.getElementById("disconnected_text");
 
 let in_lobby = true;
 let rooms = [];
+// games in progress as [room id, name, spectator count]
+let games = [];
+let spectating = false;
 let myRoom = 0;
 
 let playerColor = "";
 let square_clicked = [];
 let possible_moves = [];
 let last_moves = []
+let san_moves = [];
 let is_game_over = false;
 let game_started = false;
 let rematch_sent = false;
 let in_check = [];
+// {"white": ms, "black": ms, "running": "White"} as of clock_received, null in untimed games
+let clock = null;
+let clock_received = 0;
+// what the opponent offers and waits for an answer to, "Draw" or "Takeback"
+let pending_offer = null;
+let offer_text = "";
 
 let empty_board = [
     "RNBQKBNR",
@@ -86,8 +110,14 @@ function draw() {
         else {
             postGameHTML.style.display = "none";
         }
+        offerHTML.style.display = offer_text !== "" && !is_game_over ? "block" : "none";
+        playerControlsHTML.style.display = spectating ? "none" : "inline";
+        rematchButtonHTML.style.display = spectating ? "none" : "inline";
+        offerAnswerHTML.style.display = pending_offer !== null ? "block" : "none";
+        offerTextHTML.textContent = offer_text;
         draw_board();
         display_captured_pieces();
+        display_move_list();
     }
     else {
         lobbyHTML.style.display = "block";
@@ -111,6 +141,21 @@ function draw() {
             trElement.appendChild(td2);
             roomsHTML.appendChild(trElement);
         });
+
+        games.forEach(game => {
+            let trElement = document.createElement("tr");
+            let td1 = document.createElement("td");
+            td1.textContent = game[1] + " (" + game[2] + " watching)";
+            let td2 = document.createElement("td");
+            let button = document.createElement("button");
+            button.onclick = () => spectateGameButton(game[0]);
+            button.textContent = "Watch";
+
+            trElement.appendChild(td1);
+            td2.appendChild(button);
+            trElement.appendChild(td2);
+            roomsHTML.appendChild(trElement);
+        });
     }
 }
 
@@ -273,8 +318,37 @@ function display_captured_pieces() {
     }
 }
 
+function display_move_list() {
+    let text = "";
+    san_moves.forEach((san, i) => {
+        if (i % 2 === 0) {
+            text += (i / 2 + 1) + ". ";
+        }
+        text += san + " ";
+    });
+    moveListHTML.textContent = text;
+}
+
+// the engine only plays standard chess and Chess960
+function variant_changed() {
+    let engine_plays = variantHTML.value === "Standard" || variantHTML.value === "Chess960";
+    for (let option of opponentHTML.options) {
+        option.disabled = !engine_plays && option.value !== "human";
+    }
+    if (!engine_plays) {
+        opponentHTML.value = "human";
+    }
+}
+
 function createGameButton() {
-    let msg = {"msg_type": "Create", "room_id": 0, "room_name": nameFieldHTML.value};
+    let msg = {"msg_type": "Create", "room_id": 0, "room_name": nameFieldHTML.value, "variant": variantHTML.value};
+    if (opponentHTML.value !== "human") {
+        msg["engine"] = {"skill": parseInt(opponentHTML.value)};
+    }
+    let time_control = parse_time_control(timeControlHTML.value);
+    if (time_control !== null) {
+        msg["time_control"] = time_control;
+    }
     send_socket(msg);
 }
 
@@ -283,6 +357,11 @@ function joinGameButton(room) {
     send_socket(msg);
 }
 
+function spectateGameButton(room) {
+    let msg = {"msg_type": "Spectate", "room_id": room};
+    send_socket(msg);
+}
+
 function compare_arrays(a, b) {
     if (a.length !== b.length) {
         return false;
@@ -297,7 +376,7 @@ function compare_arrays(a, b) {
 
 function make_move(first_click, second_click) {
     if (first_click.length === 2 && second_click.length === 2) {
-        let msg = {"msg_type": "Move", "make_move": [first_click, second_click], "room_id": myRoom};
+        let msg = {"msg_type": "Move", "make_move": [first_click, second_click], "promotion": promotionHTML.value, "room_id": myRoom};
         send_socket(msg);
         cancel_move();
     }
@@ -324,6 +403,80 @@ function rematch_offer() {
     send_socket(msg);
 }
 
+function claim_draw() {
+    let msg = {"msg_type": "ClaimDraw", "room_id": myRoom};
+    send_socket(msg);
+}
+
+function resign() {
+    let msg = {"msg_type": "Resign", "room_id": myRoom};
+    send_socket(msg);
+}
+
+function offer_draw() {
+    let msg = {"msg_type": "OfferDraw", "room_id": myRoom};
+    send_socket(msg);
+}
+
+function request_takeback() {
+    let msg = {"msg_type": "RequestTakeback", "room_id": myRoom};
+    send_socket(msg);
+}
+
+// sends AcceptDraw, DeclineDraw, AcceptTakeback or DeclineTakeback
+function answer_offer(accept) {
+    if (pending_offer === null) {
+        return;
+    }
+    let msg = {"msg_type": (accept ? "Accept" : "Decline") + pending_offer, "room_id": myRoom};
+    send_socket(msg);
+}
+
+function show_offer(text, offer) {
+    offer_text = text;
+    pending_offer = offer;
+}
+
+// turns {"Win": ["White", "Checkmate"]} or {"Draw": "FiftyMoveRule"} into " (checkmate)" or " (fifty move rule)"
+function game_end_reason(result) {
+    if (!result) {
+        return "";
+    }
+    let reason = "Win" in result ? result["Win"][1] : result["Draw"];
+    return " (" + reason.replace(/([a-z])([A-Z])/g, "$1 $2").toLowerCase() + ")";
+}
+
+// "180+2" is three minutes with a two second increment, a trailing "d" makes the increment a Bronstein delay
+function parse_time_control(value) {
+    let match = value.match(/^(\d+)\+(\d+)(d?)$/);
+    if (match === null) {
+        return null;
+    }
+    return {"base": parseInt(match[1]) * 1000, "increment": parseInt(match[2]) * 1000, "mode": match[3] === "d" ? "Bronstein" : "Fischer"};
+}
+
+function format_time(ms) {
+    let seconds = Math.ceil(ms / 1000);
+    return Math.floor(seconds / 60) + ":" + String(seconds % 60).padStart(2, "0");
+}
+
+// the server only sends the clocks with every move, in between the running one is counted down here
+function display_clocks() {
+    if (clock === null) {
+        clockUpHTML.textContent = "";
+        clockDownHTML.textContent = "";
+        return;
+    }
+    let running = clock["running"] !== null ? clock["running"].toLowerCase() : null;
+    let time = color => Math.max(0, clock[color] - (color === running ? Date.now() - clock_received : 0));
+    if (is_game_over && running !== null) {
+        clock = {"white": time("white"), "black": time("black"), "running": null};
+    }
+    let opponent = playerColor === "white" ? "black" : "white";
+    clockDownHTML.textContent = format_time(time(playerColor));
+    clockUpHTML.textContent = format_time(time(opponent));
+}
+
 function send_socket(msg) {
     console.log("Sending message:");
     let json_msg = JSON.stringify(msg);
@@ -337,12 +490,15 @@ function reset_game() {
     square_clicked = [];
     possible_moves = [];
     last_moves = []
+    san_moves = [];
     is_game_over = false;
     game_started = false;
     rematch_sent = false;
     board_history = [];
     board_index = -1;
     in_check = [];
+    clock = null;
+    show_offer("", null);
 }
 
 function set_room_name() {
@@ -392,7 +548,7 @@ canvasHTML.addEventListener("mousedown", event => {
     if (board_index < board_history.length - 1) {
         board_index = board_history.length - 1;
     }
-    else if(game_started) {
+    else if(game_started && !spectating) {
         let coords = click_to_coords(
             playerColor,
             event.clientX - canvasHTML.getBoundingClientRect().left,
@@ -414,7 +570,7 @@ canvasHTML.addEventListener("mousedown", event => {
 });
 
 canvasHTML.addEventListener("mouseup", event => {
-    if(game_started) {
+    if(game_started && !spectating) {
         let coords = click_to_coords(
             playerColor,
             event.clientX - canvasHTML.getBoundingClientRect().left,
@@ -444,7 +600,27 @@ socket.addEventListener("message", event => {
         socket.close();
         disconnectHTML.style.display = "block";
     }
+    else if (decoded === "DrawDeclined") {
+        show_offer("Draw offer declined", null);
+    }
+    else if (decoded === "TakebackDeclined") {
+        show_offer("Takeback declined", null);
+    }
+    else if ("RoomRefused" in decoded) {
+        createRefusedHTML.textContent = decoded["RoomRefused"]["reason"];
+    }
+    else if ("Spectating" in decoded) {
+        // spectators watch from white's side
+        reset_game();
+        spectating = true;
+        gameIdHtml.textContent = decoded["Spectating"]["name"] + " (spectating)";
+        disconnectedTextHTML.textContent = "A player disconnected.";
+        myRoom = decoded["Spectating"]["room_id"];
+        playerColor = "white";
+        in_lobby = false;
+    }
     else if (decoded["msg_type"] === "NewRoom") {
+        createRefusedHTML.textContent = "";
         reset_game();
         gameIdHtml.textContent = nameFieldHTML.value;
         myRoom = decoded["room_id"];
@@ -455,15 +631,15 @@ socket.addEventListener("message", event => {
         possible_moves = decoded["possible_moves"];
     }
     else if (decoded["msg_type"] === "GameResultWhiteWon") {
-        winnerTextHTML.textContent = "Game over, white won!";
+        winnerTextHTML.textContent = "Game over, white won" + game_end_reason(decoded["result"]) + "!";
         is_game_over = true;
     }
     else if (decoded["msg_type"] === "GameResultBlackWon") {
-        winnerTextHTML.textContent = "Game over, black won!";
+        winnerTextHTML.textContent = "Game over, black won" + game_end_reason(decoded["result"]) + "!";
         is_game_over = true;
     }
     else if (decoded["msg_type"] === "GameResultDraw") {
-        winnerTextHTML.textContent = "Game over, draw!";
+        winnerTextHTML.textContent = "Game over, draw" + game_end_reason(decoded["result"]) + "!";
         is_game_over = true;
     }
     else if ("Rematch" in decoded) {
@@ -475,18 +651,49 @@ socket.addEventListener("message", event => {
             rematchTextHtml.textContent = "Your opponent offers a rematch...";
         }
     }
+    else if ("DrawOffer" in decoded) {
+        let mine = decoded["DrawOffer"]["my_offer"];
+        show_offer(mine ? "Draw offer sent..." : "Your opponent offers a draw", mine ? null : "Draw");
+    }
+    else if ("TakebackRequest" in decoded) {
+        let mine = decoded["TakebackRequest"]["my_request"];
+        show_offer(mine ? "Takeback request sent..." : "Your opponent asks for a takeback", mine ? null : "Takeback");
+    }
+    else if ("Takeback" in decoded) {
+        let plies = decoded["Takeback"]["plies"];
+        board_history.splice(board_history.length - plies, plies);
+        last_moves.splice(last_moves.length - plies, plies);
+        san_moves.splice(san_moves.length - plies, plies);
+        board_index = board_history.length - 1;
+        let check = decoded["Takeback"]["in_check"];
+        in_check = check !== null ? check : [];
+        clock = decoded["Takeback"]["clock"];
+        clock_received = Date.now();
+        show_offer("", null);
+        cancel_move();
+    }
     else if ("Board" in decoded) {
         game_started = true;
+        // a move cancels whatever was offered
+        show_offer("", null);
         board_index = board_history.length;
         board_history.push(parse_board(decoded["Board"]["current_board"]));
         let lm = decoded["Board"]["last_move"];
         last_moves.push(lm !== null ? lm : []);
+        let san = decoded["Board"]["last_move_san"];
+        if (san !== null && san !== undefined) {
+            san_moves.push(san);
+        }
         let check = decoded["Board"]["in_check"];
         in_check = check !== null ? check : [];
+        let board_clock = decoded["Board"]["clock"];
+        clock = board_clock !== null && board_clock !== undefined ? board_clock : null;
+        clock_received = Date.now();
         cancel_move();
     }
     else if ("Rooms" in decoded) {
         rooms = decoded["Rooms"]["room_names"];
+        games = decoded["Rooms"]["games"] || [];
     }
     else if ("PlayersOnline" in decoded) {
         playerOnlineHTML.textContent = "Players online: " + decoded["PlayersOnline"]["count"];
@@ -495,6 +702,8 @@ socket.addEventListener("message", event => {
     draw();
 });
 
+setInterval(display_clocks, 100);
+
 setInterval(() => {
     let msg = {"msg_type": "Ping", "room_id": myRoom};
     send_socket(msg);
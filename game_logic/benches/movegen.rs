@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use chess_logic_lib::board::{new_board, Board, Color, PieceType, Square, HEIGHT, WIDTH};
+use chess_logic_lib::moves::game_result;
+use chess_logic_lib::perft::perft;
+
+// a reconstruction of the kind of generator `allowed_moves` used before bitboards, not the code that was replaced:
+// targets are collected into hash sets, every candidate move is played on a cloned board and all the opponent's
+// attacks are worked out again; castling and promotions to anything but a queen are left out
+fn hash_set_targets(board: &Board, row: usize, col: usize) -> HashSet<Square> {
+    let piece = board.squares[row][col].expect("Only occupied squares expected");
+    let on_board = |r: i32, c: i32| (0..HEIGHT as i32).contains(&r) && (0..WIDTH as i32).contains(&c);
+    let free = |r: i32, c: i32| board.squares[r as usize][c as usize].is_none();
+    let mut targets = HashSet::new();
+    let (steps, slide): (&[(i32, i32)], bool) = match piece.kind {
+        PieceType::King => (&[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)], false),
+        PieceType::Queen => (&[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)], true),
+        PieceType::Rook => (&[(1, 0), (-1, 0), (0, 1), (0, -1)], true),
+        PieceType::Bishop => (&[(1, 1), (1, -1), (-1, 1), (-1, -1)], true),
+        PieceType::Knight => (&[(2, 1), (1, 2), (-1, 2), (-2, 1), (-2, -1), (-1, -2), (1, -2), (2, -1)], false),
+        PieceType::Pawn => {
+            let (forward, start) = if piece.color == Color::White { (1, 1) } else { (-1, HEIGHT as i32 - 2) };
+            let (r, c) = (row as i32 + forward, col as i32);
+            if on_board(r, c) && free(r, c) {
+                targets.insert(Square(r as usize, c as usize));
+                if row as i32 == start && free(r + forward, c) {
+                    targets.insert(Square((r + forward) as usize, c as usize));
+                }
+            }
+            for c in [c - 1, c + 1].into_iter().filter(|&c| on_board(r, c)) {
+                let square = Square(r as usize, c as usize);
+                if !free(r, c) || board.en_passant == Some(square) {
+                    targets.insert(square);
+                }
+            }
+            return targets;
+        }
+    };
+    for &(dr, dc) in steps {
+        let (mut r, mut c) = (row as i32 + dr, col as i32 + dc);
+        while on_board(r, c) {
+            targets.insert(Square(r as usize, c as usize));
+            if !slide || !free(r, c) {
+                break;
+            }
+            r += dr;
+            c += dc;
+        }
+    }
+    targets
+}
+
+fn hash_set_attacks(board: &Board, color: Color) -> HashSet<Square> {
+    (0..HEIGHT)
+        .flat_map(|r| (0..WIDTH).map(move |c| (r, c)))
+        .filter(|&(r, c)| board.squares[r][c].is_some_and(|p| p.color == color))
+        .flat_map(|(r, c)| hash_set_targets(board, r, c))
+        .collect()
+}
+
+fn legacy_perft(board: &Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let color = board.color_to_play();
+    let mut nodes = 0;
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            if !board.squares[row][col].is_some_and(|p| p.color == color) {
+                continue;
+            }
+            for to in hash_set_targets(board, row, col) {
+                if board.squares[to.0][to.1].is_some_and(|p| p.color == color) {
+                    continue;
+                }
+                let mut next = board.clone();
+                next.make_move(board.create_move(Square(row, col), to, None).unwrap());
+                if !hash_set_attacks(&next, color.opposite()).contains(&next.king_positions[&color]) {
+                    nodes += legacy_perft(&next, depth - 1);
+                }
+            }
+        }
+    }
+    nodes
+}
+
+fn time<T>(f: impl Fn() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn compare(name: &str, board: &Board, depth: u32) {
+    let (legacy_nodes, legacy_time) = time(|| legacy_perft(board, depth));
+    let (nodes, bitboard_time) = time(|| perft(board, depth));
+    assert_eq!(nodes, legacy_nodes);
+    println!("{:<10} depth {} {:>8} nodes   legacy {:>8.1} ms   bitboard {:>7.1} ms   {:>6.1}x",
+             name, depth, nodes,
+             legacy_time.as_secs_f64() * 1000.0, bitboard_time.as_secs_f64() * 1000.0,
+             legacy_time.as_secs_f64() / bitboard_time.as_secs_f64());
+}
+
+// run with `cargo bench`
+fn main() {
+    compare("initial", &new_board(), 3);
+    compare("endgame", &Board::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap(), 4);
+
+    let board = Board::from_fen("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10").unwrap();
+    let iterations = 1000;
+    let (_, elapsed) = time(|| {
+        for _ in 0..iterations {
+            black_box(game_result(black_box(&board)));
+        }
+    });
+    println!("game_result {:.1} us per call", elapsed.as_secs_f64() * 1_000_000.0 / iterations as f64);
+}
@@ -0,0 +1,35 @@
+use std::env;
+use std::process::exit;
+use std::time::Instant;
+
+use chess_logic_lib::board::{Board, STARTING_FEN};
+use chess_logic_lib::perft::perft_divide;
+
+// usage: perft <depth> [fen]
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    // depth 0 has no moves to divide by
+    let Some(depth) = args.first().and_then(|d| d.parse::<u32>().ok()).filter(|&d| d > 0) else {
+        eprintln!("usage: perft <depth> [fen], with a depth of at least 1");
+        exit(1);
+    };
+    let fen = if args.len() > 1 { args[1..].join(" ") } else { STARTING_FEN.to_string() };
+    let board = match Board::from_fen(&fen) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("invalid fen: {}", e);
+            exit(1);
+        }
+    };
+
+    let start = Instant::now();
+    let divide = perft_divide(&board, depth);
+    for (name, nodes) in &divide {
+        println!("{}: {}", name, nodes);
+    }
+    let total: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+    println!();
+    println!("Moves: {}", divide.len());
+    println!("Nodes searched: {}", total);
+    println!("Time: {} ms", start.elapsed().as_millis());
+}
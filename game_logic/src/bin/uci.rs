@@ -0,0 +1,193 @@
+use std::io::{stdin, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{park, spawn, JoinHandle};
+use std::time::Duration;
+
+use chess_logic_lib::board::{new_board, Board, CastlingRights, Color};
+use chess_logic_lib::search::{SearchLimits, SearchResult, Searcher, DEFAULT_HASH_MB};
+use chess_logic_lib::uci_engine::{uci_move, MAX_SKILL};
+
+const ENGINE_NAME: &str = "rust_chess";
+const MAX_HASH_MB: usize = 1024;
+// moves left in the game when the GUI does not say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+// kept back from the clock for the time it takes the GUI to get our move
+const MOVE_OVERHEAD_MS: u64 = 50;
+
+struct Engine {
+    board: Board,
+    // `None` while a search thread owns it
+    searcher: Option<Searcher>,
+    search: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    chess960: bool,
+    skill: u32,
+}
+
+impl Engine {
+    // blocks until the running search (if any) has printed its best move
+    fn wait(&mut self) -> &mut Searcher {
+        if let Some(search) = self.search.take() {
+            self.searcher = Some(search.join().expect("Search thread panicked"));
+        }
+        self.searcher.as_mut().expect("Searcher is back once the search is over")
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search) = &self.search {
+            search.thread().unpark();
+        }
+        self.wait();
+    }
+
+    // after `go infinite` the best move is held back until the GUI says stop, even if the search ends on its own
+    fn go(&mut self, mut limits: SearchLimits, infinite: bool) {
+        if let Some(depth) = skill_depth(self.skill) {
+            limits.depth = Some(limits.depth.map_or(depth, |limit| limit.min(depth)));
+        }
+        self.wait();
+        let mut searcher = self.searcher.take().expect("No search is running");
+        self.stop.store(false, Ordering::Relaxed);
+        let board = self.board.clone();
+        let chess960 = self.chess960;
+        let stop = self.stop.clone();
+        self.search = Some(spawn(move || {
+            let result = searcher.search_with(&board, limits, |info| println!("{}", info_line(info, board.castling_rights, chess960)));
+            while infinite && !stop.load(Ordering::Relaxed) {
+                park();
+            }
+            let best = result.best_move.map_or("0000".to_string(), |m| uci_move(&m, board.castling_rights, chess960));
+            match result.pv.get(1) {
+                Some(ponder) => println!("bestmove {} ponder {}", best, uci_move(ponder, board.castling_rights, chess960)),
+                None => println!("bestmove {}", best),
+            }
+            searcher
+        }));
+    }
+}
+
+// below full strength the search is cut off at a shallow depth, from 1 ply at skill 0 to 5 plies at skill 19
+fn skill_depth(skill: u32) -> Option<u32> {
+    (skill < MAX_SKILL).then_some(1 + skill / 4)
+}
+
+fn info_line(info: &SearchResult, rights: CastlingRights, chess960: bool) -> String {
+    let score = match info.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let millis = info.elapsed.as_millis().max(1);
+    let pv: Vec<String> = info.pv.iter().map(|m| uci_move(m, rights, chess960)).collect();
+    format!("info depth {} score {} nodes {} nps {} time {} pv {}",
+            info.depth, score, info.nodes, info.nodes as u128 * 1000 / millis, info.elapsed.as_millis(), pv.join(" "))
+}
+
+// position [startpos | fen <fen>] [moves <move>...]
+fn parse_position(tokens: &[&str]) -> Result<Board, String> {
+    let moves_at = tokens.iter().position(|&t| t == "moves").unwrap_or(tokens.len());
+    let mut board = match tokens.first() {
+        Some(&"startpos") => new_board(),
+        Some(&"fen") => Board::from_fen(&tokens[1..moves_at].join(" ")).map_err(|e| e.to_string())?,
+        _ => return Err("expected startpos or fen".to_string()),
+    };
+    for text in tokens.iter().skip(moves_at + 1) {
+        let m = board.try_parse_move(text).map_err(|e| e.to_string())?;
+        board.try_make_move(m).map_err(|e| e.to_string())?;
+    }
+    Ok(board)
+}
+
+// go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>] [movetime <ms>] [depth <n>] [nodes <n>] [infinite]
+fn parse_go(tokens: &[&str], color: Color) -> SearchLimits {
+    let value = |name: &str| tokens.iter().position(|&t| t == name)
+        .and_then(|i| tokens.get(i + 1))
+        .and_then(|v| v.parse::<u64>().ok());
+    let (clock, increment) = if color == Color::White { ("wtime", "winc") } else { ("btime", "binc") };
+    let time = match value("movetime") {
+        Some(millis) => Some(millis),
+        None if tokens.contains(&"infinite") => None,
+        None => value(clock).map(|left| {
+            let budget = left / value("movestogo").unwrap_or(DEFAULT_MOVES_TO_GO).max(1) + value(increment).unwrap_or(0) * 3 / 4;
+            budget.min(left.saturating_sub(MOVE_OVERHEAD_MS) / 2).max(1)
+        }),
+    };
+    SearchLimits {
+        depth: value("depth").map(|depth| depth as u32),
+        nodes: value("nodes"),
+        time: time.map(Duration::from_millis),
+    }
+}
+
+// setoption name <name> [value <value>]
+fn set_option(engine: &mut Engine, tokens: &[&str]) {
+    let value_at = tokens.iter().position(|&t| t == "value").unwrap_or(tokens.len());
+    let name = tokens.get(1..value_at).unwrap_or_default().join(" ");
+    let value = tokens.get(value_at + 1..).unwrap_or_default().join(" ");
+    match name.to_lowercase().as_str() {
+        "hash" => match value.parse::<usize>() {
+            Ok(mb) => engine.wait().resize_table(mb.clamp(1, MAX_HASH_MB)),
+            Err(_) => println!("info string invalid Hash value '{}'", value),
+        },
+        "uci_chess960" => engine.chess960 = value == "true",
+        "skill level" => match value.parse::<u32>() {
+            Ok(skill) => engine.skill = skill.min(MAX_SKILL),
+            Err(_) => println!("info string invalid Skill Level value '{}'", value),
+        },
+        _ => println!("info string unknown option '{}'", name),
+    }
+}
+
+fn main() {
+    let searcher = Searcher::new(DEFAULT_HASH_MB);
+    let mut engine = Engine {
+        board: new_board(),
+        stop: searcher.stop.clone(),
+        searcher: Some(searcher),
+        search: None,
+        chess960: false,
+        skill: MAX_SKILL,
+    };
+
+    for line in stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {} contributors", ENGINE_NAME);
+                println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+                println!("option name UCI_Chess960 type check default false");
+                println!("option name Skill Level type spin default {} min 0 max {}", MAX_SKILL, MAX_SKILL);
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"setoption") => set_option(&mut engine, &tokens[1..]),
+            Some(&"ucinewgame") => {
+                engine.stop();
+                engine.wait().clear();
+                engine.board = new_board();
+            }
+            Some(&"position") => {
+                engine.stop();
+                match parse_position(&tokens[1..]) {
+                    Ok(board) => engine.board = board,
+                    Err(e) => println!("info string invalid position: {}", e),
+                }
+            }
+            Some(&"go") => {
+                engine.stop();
+                let limits = parse_go(&tokens[1..], engine.board.color_to_play());
+                engine.go(limits, tokens.contains(&"infinite"));
+            }
+            Some(&"stop") => engine.stop(),
+            Some(&"quit") => break,
+            Some(other) => println!("info string unknown command '{}'", other),
+            None => {}
+        }
+    }
+    engine.stop();
+}
@@ -0,0 +1,515 @@
+use crate::board::{Board, CastlingRights, Color, MoveKind, Piece, PieceType, Square, HEIGHT, WIDTH};
+use crate::board::Color::{Black, White};
+use crate::zobrist;
+
+// one bit per square, bit `row * 8 + col`, so a1 is bit 0 and h8 is bit 63
+pub type Bitboard = u64;
+
+const KINDS: [PieceType; 6] = [PieceType::King, PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight, PieceType::Pawn];
+
+// the first four directions run towards higher square indexes, the last four towards lower ones
+const DIRECTIONS: [(i8, i8); 8] = [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (0, -1), (-1, -1), (-1, 1)];
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+
+const KNIGHT_ATTACKS: [Bitboard; 64] = step_attacks(&[(2, 1), (1, 2), (-1, 2), (-2, 1), (-2, -1), (-1, -2), (1, -2), (2, -1)]);
+pub(crate) const KING_ATTACKS: [Bitboard; 64] = step_attacks(&DIRECTIONS);
+const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [step_attacks(&[(1, -1), (1, 1)]), step_attacks(&[(-1, -1), (-1, 1)])];
+const RAYS: [[Bitboard; 64]; 8] = rays();
+
+const fn on_board(row: i8, col: i8) -> bool {
+    row >= 0 && row < HEIGHT as i8 && col >= 0 && col < WIDTH as i8
+}
+
+const fn step_attacks(steps: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let (row, col) = ((square / WIDTH) as i8, (square % WIDTH) as i8);
+        let mut i = 0;
+        while i < steps.len() {
+            let (r, c) = (row + steps[i].0, col + steps[i].1);
+            if on_board(r, c) {
+                table[square] |= 1 << (r as usize * WIDTH + c as usize);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn rays() -> [[Bitboard; 64]; 8] {
+    let mut table = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < DIRECTIONS.len() {
+        let mut square = 0;
+        while square < 64 {
+            let (mut r, mut c) = ((square / WIDTH) as i8 + DIRECTIONS[direction].0, (square % WIDTH) as i8 + DIRECTIONS[direction].1);
+            while on_board(r, c) {
+                table[direction][square] |= 1 << (r as usize * WIDTH + c as usize);
+                r += DIRECTIONS[direction].0;
+                c += DIRECTIONS[direction].1;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    table
+}
+
+// the ray stops at (and includes) the first occupied square
+fn ray_attacks(direction: usize, square: u8, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[direction][square as usize];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let first = if direction < 4 { blockers.trailing_zeros() } else { 63 - blockers.leading_zeros() };
+    ray ^ RAYS[direction][first as usize]
+}
+
+fn rook_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    ROOK_DIRECTIONS.iter().fold(0, |attacks, &d| attacks | ray_attacks(d, square, occupied))
+}
+
+fn bishop_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    BISHOP_DIRECTIONS.iter().fold(0, |attacks, &d| attacks | ray_attacks(d, square, occupied))
+}
+
+pub(crate) fn squares_of(mut bitboard: Bitboard) -> impl Iterator<Item = u8> {
+    std::iter::from_fn(move || {
+        (bitboard != 0).then(|| {
+            let square = bitboard.trailing_zeros() as u8;
+            bitboard &= bitboard - 1;
+            square
+        })
+    })
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        White => 0,
+        Black => 1,
+    }
+}
+
+fn kind_index(kind: PieceType) -> usize {
+    KINDS.iter().position(|&k| k == kind).expect("Every kind is listed")
+}
+
+pub fn square_index(square: Square) -> u8 {
+    (square.0 * WIDTH + square.1) as u8
+}
+
+pub fn square_coordinates(index: u8) -> Square {
+    Square(index as usize / WIDTH, index as usize % WIDTH)
+}
+
+pub fn square_bit(square: Square) -> Bitboard {
+    1 << square_index(square)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BitMove {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: Option<PieceType>,
+}
+
+impl BitMove {
+    pub fn move_from(&self) -> Square {
+        square_coordinates(self.from)
+    }
+
+    pub fn move_to(&self) -> Square {
+        square_coordinates(self.to)
+    }
+}
+
+// everything `make_move` overwrites and cannot recompute on the way back
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Undo {
+    piece: Piece,
+    captured: Option<(Piece, u8)>,
+    castling: Option<MoveKind>,
+    side_to_move: Color,
+    castling_rights: CastlingRights,
+    en_passant: Option<u8>,
+    hash: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Position {
+    pieces: [[Bitboard; 6]; 2],
+    occupied: [Bitboard; 2],
+    pub side_to_move: Color,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<u8>,
+    // the same key `Board::hash` gives, kept up to date by `make_move`
+    hash: u64,
+}
+
+impl Position {
+    // `side_to_move` may differ from the board's, the en passant square is only kept if it belongs to that side
+    pub fn from_board(board: &Board, side_to_move: Color) -> Position {
+        let en_passant_row = if side_to_move == White { HEIGHT - 3 } else { 2 };
+        let mut position = Position {
+            pieces: [[0; 6]; 2],
+            occupied: [0; 2],
+            side_to_move,
+            castling_rights: board.castling_rights,
+            en_passant: board.en_passant.filter(|square| square.row() == en_passant_row).map(square_index),
+            hash: 0,
+        };
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                if let Some(piece) = board.squares[row][col] {
+                    position.put(piece, square_index(Square(row, col)));
+                }
+            }
+        }
+        // the search does not count checks, whatever the board had given stays in the key
+        position.hash ^= position.state_key() ^ zobrist::checks_key(board.checks);
+        position
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // the side to move, castling rights and en passant part of the hash
+    fn state_key(&self) -> u64 {
+        zobrist::side_to_move_key(self.side_to_move)
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ zobrist::en_passant_key(self.en_passant_target().map(square_coordinates))
+    }
+
+    // the en passant square only counts towards the position if a pawn can legally capture there
+    pub fn en_passant_target(&self) -> Option<u8> {
+        self.en_passant.filter(|&square| {
+            squares_of(self.pawns_attacking(square, self.side_to_move))
+                .any(|from| self.is_legal(BitMove { from, to: square, promotion: None }))
+        })
+    }
+
+    pub fn piece_at(&self, square: u8) -> Option<Piece> {
+        let bit = 1 << square;
+        let color = if self.occupied[0] & bit != 0 { White } else if self.occupied[1] & bit != 0 { Black } else { return None };
+        let kind = KINDS.iter().copied().find(|&k| self.pieces[color_index(color)][kind_index(k)] & bit != 0)?;
+        Some(Piece { color, kind })
+    }
+
+    fn put(&mut self, piece: Piece, square: u8) {
+        self.pieces[color_index(piece.color)][kind_index(piece.kind)] |= 1 << square;
+        self.occupied[color_index(piece.color)] |= 1 << square;
+        self.hash ^= zobrist::piece_key(piece, square_coordinates(square));
+    }
+
+    fn remove(&mut self, piece: Piece, square: u8) {
+        self.pieces[color_index(piece.color)][kind_index(piece.kind)] &= !(1 << square);
+        self.occupied[color_index(piece.color)] &= !(1 << square);
+        self.hash ^= zobrist::piece_key(piece, square_coordinates(square));
+    }
+
+    pub fn pieces(&self, color: Color, kind: PieceType) -> Bitboard {
+        self.pieces[color_index(color)][kind_index(kind)]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.occupied[0] | self.occupied[1]
+    }
+
+    pub fn occupied_by(&self, color: Color) -> Bitboard {
+        self.occupied[color_index(color)]
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<u8> {
+        squares_of(self.pieces(color, PieceType::King)).next()
+    }
+
+    pub(crate) fn piece_attacks(&self, piece: Piece, square: u8) -> Bitboard {
+        let occupied = self.occupied();
+        match piece.kind {
+            PieceType::King => KING_ATTACKS[square as usize],
+            PieceType::Queen => rook_attacks(square, occupied) | bishop_attacks(square, occupied),
+            PieceType::Rook => rook_attacks(square, occupied),
+            PieceType::Bishop => bishop_attacks(square, occupied),
+            PieceType::Knight => KNIGHT_ATTACKS[square as usize],
+            PieceType::Pawn => PAWN_ATTACKS[color_index(piece.color)][square as usize],
+        }
+    }
+
+    // every square attacked by `color`, including squares defended by its own pieces
+    pub fn attacks(&self, color: Color) -> Bitboard {
+        KINDS.iter()
+            .flat_map(|&kind| squares_of(self.pieces(color, kind)).map(move |square| (Piece { color, kind }, square)))
+            .fold(0, |attacks, (piece, square)| attacks | self.piece_attacks(piece, square))
+    }
+
+    pub fn is_attacked(&self, square: u8, by: Color) -> bool {
+        let occupied = self.occupied();
+        let sliders = |kind: PieceType| self.pieces(by, kind) | self.pieces(by, PieceType::Queen);
+        KNIGHT_ATTACKS[square as usize] & self.pieces(by, PieceType::Knight) != 0 ||
+            KING_ATTACKS[square as usize] & self.pieces(by, PieceType::King) != 0 ||
+            self.pawns_attacking(square, by) != 0 ||
+            bishop_attacks(square, occupied) & sliders(PieceType::Bishop) != 0 ||
+            rook_attacks(square, occupied) & sliders(PieceType::Rook) != 0
+    }
+
+    // the pawns of `by` which could capture on `square`
+    pub fn pawns_attacking(&self, square: u8, by: Color) -> Bitboard {
+        PAWN_ATTACKS[color_index(by.opposite())][square as usize] & self.pieces(by, PieceType::Pawn)
+    }
+
+    pub fn in_check(&self, color: Color) -> bool {
+        self.king_square(color).is_some_and(|king| self.is_attacked(king, color.opposite()))
+    }
+
+    // where the piece on `from` could go if its own king were not in danger, castling is not included
+    pub fn pseudo_legal_targets(&self, from: u8) -> Bitboard {
+        let Some(piece) = self.piece_at(from) else {
+            return 0;
+        };
+        let own = self.occupied[color_index(piece.color)];
+        let opponent = self.occupied[color_index(piece.color.opposite())];
+        match piece.kind {
+            PieceType::Pawn => {
+                let Square(row, col) = square_coordinates(from);
+                let (forward, start_row) = if piece.color == White { (1, 1) } else { (-1, HEIGHT as i8 - 2) };
+                let mut targets = PAWN_ATTACKS[color_index(piece.color)][from as usize] & (opponent | self.en_passant.map_or(0, |s| 1 << s));
+                let one = row as i8 + forward;
+                if on_board(one, col as i8) && self.occupied() & square_bit(Square(one as usize, col)) == 0 {
+                    targets |= square_bit(Square(one as usize, col));
+                    let two = one + forward;
+                    if row as i8 == start_row && self.occupied() & square_bit(Square(two as usize, col)) == 0 {
+                        targets |= square_bit(Square(two as usize, col));
+                    }
+                }
+                targets
+            }
+            _ => self.piece_attacks(piece, from) & !own,
+        }
+    }
+
+    fn pseudo_legal_moves_from(&self, from: u8, moves: &mut Vec<BitMove>) {
+        let Some(piece) = self.piece_at(from) else {
+            return;
+        };
+        if piece.kind == PieceType::King {
+            self.castling_moves(piece.color, from, moves);
+        }
+        for to in squares_of(self.pseudo_legal_targets(from)) {
+            let last_row = if piece.color == White { HEIGHT - 1 } else { 0 };
+            if piece.kind == PieceType::Pawn && square_coordinates(to).0 == last_row {
+                for kind in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                    moves.push(BitMove { from, to, promotion: Some(kind) });
+                }
+            }
+            else {
+                moves.push(BitMove { from, to, promotion: None });
+            }
+        }
+    }
+
+    // the king may not castle out of or through check, landing in check is left to the legality filter
+    fn castling_moves(&self, color: Color, from: u8, moves: &mut Vec<BitMove>) {
+        let rights = self.castling_rights;
+        let row = if color == White { 0 } else { HEIGHT - 1 };
+        if from != square_index(Square(row, rights.king_file)) || self.is_attacked(from, color.opposite()) {
+            return;
+        }
+        for (kind, allowed) in [(MoveKind::CastleKingSide, rights.king_side(color)), (MoveKind::CastleQueenSide, rights.queen_side(color))] {
+            let (Some((rook_from, rook_to)), Some(king_file)) = (rights.castling_rook(kind, row), kind.castling_king_file()) else {
+                continue;
+            };
+            // the rights alone are not enough, a board put together square by square can claim them without a rook in the corner
+            if !allowed || self.pieces(color, PieceType::Rook) & square_bit(rook_from) == 0 {
+                continue;
+            }
+            // whatever the king or the rook passes over or lands on must be empty, apart from the two of them
+            let king_path = rank_span(row, rights.king_file, king_file);
+            let others = self.occupied() & !(1 << from) & !square_bit(rook_from);
+            if (king_path | rank_span(row, rook_from.1, rook_to.1)) & others != 0 {
+                continue;
+            }
+            let crossed = king_path & !(1 << from) & !square_bit(Square(row, king_file));
+            if squares_of(crossed).any(|square| self.is_attacked(square, color.opposite())) {
+                continue;
+            }
+            // outside the standard setup castling is written as the king taking its own rook
+            let to = if rights.standard_setup() { Square(row, king_file) } else { rook_from };
+            moves.push(BitMove { from, to: square_index(to), promotion: None });
+        }
+    }
+
+    fn is_legal(&self, m: BitMove) -> bool {
+        let color = self.piece_at(m.from).expect("Move must start on an occupied square").color;
+        let mut next = *self;
+        next.move_pieces(m);
+        !next.in_check(color)
+    }
+
+    pub fn legal_moves_from(&self, from: u8) -> Vec<BitMove> {
+        let mut moves = Vec::new();
+        self.pseudo_legal_moves_from(from, &mut moves);
+        moves.retain(|&m| self.is_legal(m));
+        moves
+    }
+
+    pub fn legal_moves(&self) -> Vec<BitMove> {
+        let mut moves = Vec::new();
+        for from in squares_of(self.occupied[color_index(self.side_to_move)]) {
+            self.pseudo_legal_moves_from(from, &mut moves);
+        }
+        moves.retain(|&m| self.is_legal(m));
+        moves
+    }
+
+    pub fn has_legal_move(&self) -> bool {
+        let mut moves = Vec::new();
+        squares_of(self.occupied[color_index(self.side_to_move)]).any(|from| {
+            moves.clear();
+            self.pseudo_legal_moves_from(from, &mut moves);
+            moves.iter().any(|&m| self.is_legal(m))
+        })
+    }
+
+    // mirrors `Board::make_move`, a pawn reaching the last rank without a promotion becomes a queen
+    pub fn make_move(&mut self, m: BitMove) -> Undo {
+        // the old state leaves the hash before it changes, the pieces keep it up to date as they move
+        let old_state = self.state_key();
+        let undo = self.move_pieces(m);
+        self.hash ^= old_state ^ self.state_key();
+        undo
+    }
+
+    // `make_move` without the side to move, castling and en passant keys, which is all a legality test needs
+    fn move_pieces(&mut self, m: BitMove) -> Undo {
+        let piece = self.piece_at(m.from).expect("Move must start on an occupied square");
+        let (from, to) = (m.move_from(), m.move_to());
+        let castling = self.castling_rights.castling_kind(piece, from, to, self.piece_at(m.to));
+        let mut captured = self.piece_at(m.to).filter(|_| castling.is_none()).map(|p| (p, m.to));
+        if piece.kind == PieceType::Pawn && captured.is_none() && from.1 != to.1 {
+            let square = square_index(Square(from.0, to.1));
+            captured = self.piece_at(square)
+                .filter(|p| p.kind == PieceType::Pawn && p.color != piece.color)
+                .map(|p| (p, square));
+        }
+        let undo = Undo {
+            piece,
+            captured,
+            castling,
+            side_to_move: self.side_to_move,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            hash: self.hash,
+        };
+
+        if let Some((p, square)) = captured {
+            self.remove(p, square);
+        }
+        self.remove(piece, m.from);
+        if let Some((rook_from, rook_to)) = castling.and_then(|kind| self.castling_rights.castling_rook(kind, from.0)) {
+            let rook = Piece { color: piece.color, kind: PieceType::Rook };
+            self.remove(rook, square_index(rook_from));
+            self.put(rook, square_index(rook_to));
+        }
+        let is_promotion = piece.kind == PieceType::Pawn && (to.0 == 0 || to.0 == HEIGHT - 1);
+        let placed = if is_promotion { Piece { color: piece.color, kind: m.promotion.unwrap_or(PieceType::Queen) } } else { piece };
+        self.put(placed, destination(m, castling));
+
+        self.castling_rights.remove_for_square(from);
+        self.castling_rights.remove_for_square(to);
+        self.en_passant = (piece.kind == PieceType::Pawn && (from.0 + 2 == to.0 || to.0 + 2 == from.0))
+            .then_some(square_index(Square((from.0 + to.0) / 2, from.1)));
+        self.side_to_move = piece.color.opposite();
+        undo
+    }
+
+    pub fn unmake_move(&mut self, m: BitMove, undo: Undo) {
+        let destination = destination(m, undo.castling);
+        let placed = self.piece_at(destination).expect("Moved piece must be on its target square");
+        self.remove(placed, destination);
+        if let Some((rook_from, rook_to)) = undo.castling.and_then(|kind| undo.castling_rights.castling_rook(kind, m.move_from().0)) {
+            let rook = Piece { color: undo.piece.color, kind: PieceType::Rook };
+            self.remove(rook, square_index(rook_to));
+            self.put(rook, square_index(rook_from));
+        }
+        self.put(undo.piece, m.from);
+        if let Some((p, square)) = undo.captured {
+            self.put(p, square);
+        }
+        self.side_to_move = undo.side_to_move;
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.hash = undo.hash;
+    }
+}
+
+// where the moving piece ends up, see `Move::destination`
+fn destination(m: BitMove, castling: Option<MoveKind>) -> u8 {
+    castling.and_then(|kind| kind.castling_king_file())
+        .map_or(m.to, |file| square_index(Square(m.move_from().0, file)))
+}
+
+// the squares of `row` from one file to the other, both included
+fn rank_span(row: usize, a: usize, b: usize) -> Bitboard {
+    (a.min(b)..=a.max(b)).fold(0, |bits, col| bits | square_bit(Square(row, col)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::{square_index, Position};
+    use crate::board::{new_board, Board, Square};
+    use crate::board::Color::{Black, White};
+
+    #[test]
+    fn test_make_unmake_restores_position() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/8/k2pP2R/8/8/4K3 b - e3 0 1",
+        ];
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            let mut position = Position::from_board(&board, board.color_to_play());
+            let original = position;
+            for m in original.legal_moves() {
+                let undo = position.make_move(m);
+                assert_ne!(position, original);
+                position.unmake_move(m, undo);
+                assert_eq!(position, original, "{} {:?}", fen, m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_make_move_matches_board() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let position = Position::from_board(&board, White);
+        for m in position.legal_moves() {
+            let mut after = position;
+            after.make_move(m);
+            let mut board_after = board.clone();
+            board_after.make_move(board.create_move(m.move_from(), m.move_to(), m.promotion).unwrap());
+            assert_eq!(after, Position::from_board(&board_after, Black), "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn test_attacks() {
+        let position = Position::from_board(&new_board(), White);
+        // every square of the first three ranks but the rook corners
+        assert_eq!(position.attacks(White), 0x0000_0000_00ff_ff7e);
+        assert_eq!(position.attacks(Black), 0x7eff_ff00_0000_0000);
+        assert!(position.is_attacked(square_index(Square(2, 0)), White));
+        assert!(!position.is_attacked(square_index(Square(3, 0)), White));
+        assert!(!position.in_check(White));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        let position = Position::from_board(&board, Black);
+        assert!(position.in_check(Black));
+        assert!(position.has_legal_move());
+    }
+}
@@ -1,11 +1,62 @@
 use std::collections::HashMap;
+use std::fmt;
 use serde::{Serializer};
 use crate::board::Color::{Black, White};
+use crate::bitboard::Position;
+use crate::moves;
+use crate::variant::{GameVariant, CHECKS_TO_WIN};
+use crate::zobrist;
 
 pub const WIDTH: usize = 8;
 pub const HEIGHT: usize = 8;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// (row, col) with row 0 being white's back rank, serialized as a `[row, col]` pair
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct Square(pub usize, pub usize);
+
+impl Square {
+    pub fn row(&self) -> usize {
+        self.0
+    }
+
+    pub fn col(&self) -> usize {
+        self.1
+    }
+
+    // the fields are public, this is the checked way of building a square
+    pub fn new(row: usize, col: usize) -> Result<Square, ChessError> {
+        if row < HEIGHT && col < WIDTH {
+            Ok(Square(row, col))
+        } else {
+            Err(ChessError::OffBoard(row, col))
+        }
+    }
+
+    // algebraic name such as "e4"
+    pub fn parse(name: &str) -> Option<Square> {
+        let bytes = name.as_bytes();
+        if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+            return None;
+        }
+        Some(Square((bytes[1] - b'1') as usize, (bytes[0] - b'a') as usize))
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.1 as u8) as char, self.0 + 1)
+    }
+}
+
+impl From<(usize, usize)> for Square {
+    fn from((row, col): (usize, usize)) -> Self {
+        Square(row, col)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum PieceType {
     King,
     Queen,
@@ -30,11 +81,32 @@ impl Color {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WinReason {
+    Checkmate,
+    Resignation,
+    Timeout,
+    // the opponent left the game
+    Abandonment,
+    KingOfTheHill,
+    ThreeCheck,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DrawReason {
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    InsufficientMaterial,
+    Agreement,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum GameStatus {
     InProgress,
-    Win(Color),
-    Draw
+    Win(Color, WinReason),
+    Draw(DrawReason)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -47,78 +119,803 @@ impl Piece {
     fn new(color: Color, kind: PieceType) -> Self {
         Piece { color, kind }
     }
+
+    pub fn to_char(&self) -> char {
+        let c = match self.kind {
+            PieceType::King => 'k',
+            PieceType::Queen => 'q',
+            PieceType::Rook => 'r',
+            PieceType::Bishop => 'b',
+            PieceType::Knight => 'n',
+            PieceType::Pawn => 'p',
+        };
+        if self.color == White { c.to_ascii_uppercase() } else { c }
+    }
+
+    pub fn from_char(c: char) -> Option<Piece> {
+        let kind = match c.to_ascii_lowercase() {
+            'k' => PieceType::King,
+            'q' => PieceType::Queen,
+            'r' => PieceType::Rook,
+            'b' => PieceType::Bishop,
+            'n' => PieceType::Knight,
+            'p' => PieceType::Pawn,
+            _ => return None,
+        };
+        let color = if c.is_ascii_uppercase() { White } else { Black };
+        Some(Piece::new(color, kind))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MoveKind {
+    Normal,
+    DoublePawnPush,
+    EnPassant,
+    CastleKingSide,
+    CastleQueenSide,
+    Promotion(PieceType),
+}
+
+impl MoveKind {
+    // the file the king lands on when castling, the g file on the king side and the c file on the queen side
+    pub fn castling_king_file(&self) -> Option<usize> {
+        match self {
+            MoveKind::CastleKingSide => Some(6),
+            MoveKind::CastleQueenSide => Some(2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub piece: Piece,
+    pub captured: Option<Piece>,
+    pub kind: MoveKind,
+}
+
+impl Move {
+    pub fn promotion(&self) -> Option<PieceType> {
+        match self.kind {
+            MoveKind::Promotion(kind) => Some(kind),
+            _ => None,
+        }
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    pub fn is_castling(&self) -> bool {
+        matches!(self.kind, MoveKind::CastleKingSide | MoveKind::CastleQueenSide)
+    }
+
+    // where the captured piece stands, only differs from `to` for en passant
+    pub fn capture_square(&self) -> Square {
+        if self.kind == MoveKind::EnPassant { Square(self.from.0, self.to.1) } else { self.to }
+    }
+
+    // where the moving piece ends up, differs from `to` only when castling is written as the king taking its own rook
+    pub fn destination(&self) -> Square {
+        self.kind.castling_king_file().map_or(self.to, |file| Square(self.from.0, file))
+    }
+}
+
+// long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(kind) = self.promotion() {
+            write!(f, "{}", Piece::new(Black, kind).to_char())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+    // start files of the king and of both castling rooks, shared by the two colours since Chess960 mirrors the setup
+    pub king_file: usize,
+    pub king_side_rook_file: usize,
+    pub queen_side_rook_file: usize,
+}
+
+impl Default for CastlingRights {
+    fn default() -> Self {
+        CastlingRights {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+            king_file: 4,
+            king_side_rook_file: WIDTH - 1,
+            queen_side_rook_file: 0,
+        }
+    }
+}
+
+impl CastlingRights {
+    pub fn all() -> Self {
+        CastlingRights { white_king_side: true, white_queen_side: true, black_king_side: true, black_queen_side: true, ..Default::default() }
+    }
+
+    // king on the e file with the rooks in the corners, castling is then written as the king moving two squares
+    pub fn standard_setup(&self) -> bool {
+        self.king_file == 4 && self.king_side_rook_file == WIDTH - 1 && self.queen_side_rook_file == 0
+    }
+
+    pub fn king_side(&self, color: Color) -> bool {
+        match color {
+            White => self.white_king_side,
+            Black => self.black_king_side,
+        }
+    }
+
+    pub fn queen_side(&self, color: Color) -> bool {
+        match color {
+            White => self.white_queen_side,
+            Black => self.black_queen_side,
+        }
+    }
+
+    fn right_mut(&mut self, color: Color, king_side: bool) -> &mut bool {
+        match (color, king_side) {
+            (White, true) => &mut self.white_king_side,
+            (White, false) => &mut self.white_queen_side,
+            (Black, true) => &mut self.black_king_side,
+            (Black, false) => &mut self.black_queen_side,
+        }
+    }
+
+    // a move from or to one of these squares means the king or the rook has left (or was captured on) its start square
+    pub(crate) fn remove_for_square(&mut self, square: Square) {
+        let color = match square.0 {
+            0 => White,
+            row if row == HEIGHT - 1 => Black,
+            _ => return,
+        };
+        if square.1 == self.king_file || square.1 == self.king_side_rook_file {
+            *self.right_mut(color, true) = false;
+        }
+        if square.1 == self.king_file || square.1 == self.queen_side_rook_file {
+            *self.right_mut(color, false) = false;
+        }
+    }
+
+    // a king move is castling when it lands on its own rook (the Chess960 way of writing it, accepted in any setup)
+    // or, in the standard setup, when it steps two squares towards the rook
+    pub(crate) fn castling_kind(&self, piece: Piece, from: Square, to: Square, target: Option<Piece>) -> Option<MoveKind> {
+        let row = if piece.color == White { 0 } else { HEIGHT - 1 };
+        if piece.kind != PieceType::King || from != Square(row, self.king_file) || to.0 != row {
+            return None;
+        }
+        let onto_rook = target == Some(Piece::new(piece.color, PieceType::Rook));
+        if (onto_rook && to.1 == self.king_side_rook_file) || (self.standard_setup() && to.1 == 6) {
+            Some(MoveKind::CastleKingSide)
+        }
+        else if (onto_rook && to.1 == self.queen_side_rook_file) || (self.standard_setup() && to.1 == 2) {
+            Some(MoveKind::CastleQueenSide)
+        }
+        else {
+            None
+        }
+    }
+
+    // the rook's (from, to) squares when castling, the rook always ends up next to the king on the f or d file
+    pub fn castling_rook(&self, kind: MoveKind, row: usize) -> Option<(Square, Square)> {
+        match kind {
+            MoveKind::CastleKingSide => Some((Square(row, self.king_side_rook_file), Square(row, 5))),
+            MoveKind::CastleQueenSide => Some((Square(row, self.queen_side_rook_file), Square(row, 3))),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    WrongRankCount(usize),
+    WrongRankLength(usize),
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    // the king or a castling rook stands on different files for the two colours
+    UnsupportedCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    WrongKingCount(Color, usize),
+    PawnOnBackRank(Square),
+    OpponentInCheck,
+    InvalidChecks(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(n) => write!(f, "expected 4 or 6 fields, found {}", n),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::WrongRankLength(rank) => write!(f, "rank {} does not describe exactly 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::UnsupportedCastling(s) => write!(f, "castling rights '{}' need different king or rook files for each colour, which is not supported", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+            FenError::WrongKingCount(color, n) => write!(f, "expected one {:?} king, found {}", color, n),
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on back rank at {}", square),
+            FenError::OpponentInCheck => write!(f, "side not to move is in check"),
+            FenError::InvalidChecks(s) => write!(f, "invalid check count '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+// what the `try_` functions return instead of panicking on input they cannot handle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChessError {
+    InvalidFen(FenError),
+    InvalidMove(String),
+    OffBoard(usize, usize),
+    EmptySquare(Square),
+    WrongColor(Square),
+    MissingKing(Color),
+    IllegalMove(Move),
+}
+
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChessError::InvalidFen(e) => write!(f, "invalid FEN: {}", e),
+            ChessError::InvalidMove(text) => write!(f, "cannot parse move '{}'", text),
+            ChessError::OffBoard(row, col) => write!(f, "square ({}, {}) is off the board", row, col),
+            ChessError::EmptySquare(square) => write!(f, "no piece on {}", square),
+            ChessError::WrongColor(square) => write!(f, "the piece on {} does not belong to the side to move", square),
+            ChessError::MissingKing(color) => write!(f, "{:?} has no king", color),
+            ChessError::IllegalMove(m) => write!(f, "illegal move {}", m),
+        }
+    }
+}
+
+impl std::error::Error for ChessError {}
+
+impl From<FenError> for ChessError {
+    fn from(e: FenError) -> Self {
+        ChessError::InvalidFen(e)
+    }
+}
+
+// how many checks each side has given, only counted in Three-check games
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Checks {
+    pub white: u32,
+    pub black: u32,
+}
+
+impl Checks {
+    pub fn given(&self, color: Color) -> u32 {
+        match color {
+            White => self.white,
+            Black => self.black,
+        }
+    }
+
+    fn given_mut(&mut self, color: Color) -> &mut u32 {
+        match color {
+            White => &mut self.white,
+            Black => &mut self.black,
+        }
+    }
+
+    // the "+W+B" field lichess appends to Three-check FENs
+    fn parse(field: &str) -> Option<Checks> {
+        let (white, black) = field.strip_prefix('+')?.split_once('+')?;
+        let count = |text: &str| text.parse().ok().filter(|&n| n <= CHECKS_TO_WIN);
+        Some(Checks { white: count(white)?, black: count(black)? })
+    }
+}
+
+// what `make_move` overwrites and `unmake_move` cannot work out from the move itself
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct UndoRecord {
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    checks: Checks,
+    hash: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct Board {
     pub squares: [[Option<Piece>; WIDTH]; HEIGHT],
-    pub move_history: Vec<(Piece, (usize, usize), (usize, usize))>,
-    pub position_counter: HashMap<[[Option<Piece>; WIDTH]; HEIGHT], u32>,
-    pub max_position_count: u32,
-    pub king_positions: HashMap<Color, (usize, usize)>,
+    pub move_history: Vec<Move>,
+    pub position_counter: HashMap<u64, u32>,
+    pub king_positions: HashMap<Color, Square>,
+    pub side_to_move: Color,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<Square>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
     pub game_over: bool,
     pub name: String,
+    // the rules the game is played with, a rematch keeps them
+    pub variant: GameVariant,
+    pub checks: Checks,
+    pub(crate) hash: u64,
+    pub(crate) undo_history: Vec<UndoRecord>,
 }
 
 impl Board {
     pub fn color_to_play(&self) -> Color {
-        if self.move_history.len() % 2 == 0 { White } else { Black }
-    }
-
-    pub fn make_move(&mut self, move_from: (usize, usize), move_to: (usize, usize)) {
-        let piece = self.squares[move_from.0][move_from.1].unwrap();
-        let block_en_passant = self.squares[move_to.0][move_to.1].is_none();
-        self.move_history.push((piece, move_from, move_to));
-        self.squares[move_from.0][move_from.1] = None;
-        self.squares[move_to.0][move_to.1] = Some(piece);
-        if piece.kind == PieceType::King {
-            self.king_positions.insert(piece.color, move_to);
-            if move_from.0 == move_to.0 && move_to.1 + 2 == move_from.1 {
-                self.squares[move_from.0][3] = self.squares[move_from.0][0];
-                self.squares[move_from.0][0] = None;
+        self.side_to_move
+    }
+
+    // reads FEN, X-FEN and Shredder-FEN, castling files are shared by both colours so
+    // positions whose castling kings or rooks stand on different files for White and Black are refused.
+    // FEN has no field for King of the Hill, such a position comes back as standard chess and the caller sets `variant`
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        // a Three-check position ends with the checks each side has given
+        let checks = match fields.last().copied().filter(|field| field.starts_with('+')) {
+            Some(field) => {
+                fields.pop();
+                Some(Checks::parse(field).ok_or(FenError::InvalidChecks(field.to_string()))?)
+            }
+            None => None,
+        };
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != HEIGHT {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        let mut squares = [[None; WIDTH]; HEIGHT];
+        for (i, rank) in ranks.iter().enumerate() {
+            let row = HEIGHT - 1 - i;
+            let mut col = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|&d| (1..=8).contains(&d)) {
+                    col += empty as usize;
+                }
+                else {
+                    let piece = Piece::from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                    if col >= WIDTH {
+                        return Err(FenError::WrongRankLength(row + 1));
+                    }
+                    squares[row][col] = Some(piece);
+                    col += 1;
+                }
             }
-            else if move_from.0 == move_to.0 && move_from.1 + 2 == move_to.1 {
-                self.squares[move_from.0][5] = self.squares[move_from.0][7];
-                self.squares[move_from.0][7] = None;
+            if col != WIDTH {
+                return Err(FenError::WrongRankLength(row + 1));
             }
         }
-        else if piece.kind == PieceType::Pawn {
-            if move_to.0 == 7 && piece.color == White {
-                self.squares[move_to.0][move_to.1] = Some(Piece { color: White, kind: PieceType::Queen });
+
+        let side_to_move = match fields[1] {
+            "w" => White,
+            "b" => Black,
+            s => return Err(FenError::InvalidSideToMove(s.to_string())),
+        };
+
+        let mut castling_rights = CastlingRights::default();
+        if fields[2] != "-" {
+            let invalid = || FenError::InvalidCastling(fields[2].to_string());
+            let (mut king_file, mut king_side_rook_file, mut queen_side_rook_file) = (None, None, None);
+            for c in fields[2].chars() {
+                let color = if c.is_ascii_uppercase() { White } else { Black };
+                let row = if color == White { 0 } else { HEIGHT - 1 };
+                let has = |col: usize, kind: PieceType| squares[row][col] == Some(Piece::new(color, kind));
+                let king = (0..WIDTH).find(|&col| has(col, PieceType::King)).ok_or_else(invalid)?;
+                // K and Q stand for the outermost rook on that side, a file letter (Shredder-FEN) names the rook directly
+                let rook = match c.to_ascii_lowercase() {
+                    'k' => (king + 1..WIDTH).rev().find(|&col| has(col, PieceType::Rook)),
+                    'q' => (0..king).find(|&col| has(col, PieceType::Rook)),
+                    file @ 'a'..='h' => Some(file as usize - 'a' as usize).filter(|&col| has(col, PieceType::Rook)),
+                    _ => None,
+                }.ok_or_else(invalid)?;
+                let king_side = rook > king;
+                let rook_file = if king_side { &mut king_side_rook_file } else { &mut queen_side_rook_file };
+                let right = castling_rights.right_mut(color, king_side);
+                if *right {
+                    return Err(invalid());
+                }
+                if king_file.is_some_and(|f| f != king) || rook_file.is_some_and(|f| f != rook) {
+                    return Err(FenError::UnsupportedCastling(fields[2].to_string()));
+                }
+                *right = true;
+                *rook_file = Some(rook);
+                king_file = Some(king);
             }
-            else if move_to.0 == 0 && piece.color == Black {
-                self.squares[move_to.0][move_to.1] = Some(Piece { color: Black, kind: PieceType::Queen });
+            castling_rights.king_file = king_file.unwrap_or(castling_rights.king_file);
+            castling_rights.king_side_rook_file = king_side_rook_file.unwrap_or(castling_rights.king_side_rook_file);
+            castling_rights.queen_side_rook_file = queen_side_rook_file.unwrap_or(castling_rights.queen_side_rook_file);
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            s => {
+                let square = Square::parse(s).ok_or(FenError::InvalidEnPassant(s.to_string()))?;
+                let (ep_row, pawn_row, start_row, pawn_color) = match side_to_move {
+                    White => (5, 4, 6, Black),
+                    Black => (2, 3, 1, White),
+                };
+                if square.0 != ep_row ||
+                    squares[square.0][square.1].is_some() ||
+                    squares[start_row][square.1].is_some() ||
+                    squares[pawn_row][square.1] != Some(Piece::new(pawn_color, PieceType::Pawn)) {
+                    return Err(FenError::InvalidEnPassant(s.to_string()));
+                }
+                Some(square)
             }
-            else if move_from.1 + 1 == move_to.1 || move_to.1 + 1 == move_from.1 {
-                if self.squares[move_from.0][move_to.1].is_some_and(|x| x.kind == PieceType::Pawn && x.color != piece.color) && block_en_passant {
-                    self.squares[move_from.0][move_to.1] = None;
+        };
+
+        let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+            let halfmove: u32 = fields[4].parse().map_err(|_| FenError::InvalidHalfmoveClock(fields[4].to_string()))?;
+            let fullmove: u32 = fields[5].parse().ok().filter(|&n| n > 0)
+                .ok_or(FenError::InvalidFullmoveNumber(fields[5].to_string()))?;
+            (halfmove, fullmove)
+        } else {
+            (0, 1)
+        };
+
+        let mut king_positions = HashMap::new();
+        for color in [White, Black] {
+            let kings: Vec<Square> = (0..HEIGHT)
+                .flat_map(|r| (0..WIDTH).map(move |c| Square(r, c)))
+                .filter(|&Square(r, c)| squares[r][c] == Some(Piece::new(color, PieceType::King)))
+                .collect();
+            if kings.len() != 1 {
+                return Err(FenError::WrongKingCount(color, kings.len()));
+            }
+            king_positions.insert(color, kings[0]);
+        }
+        for row in [0, HEIGHT - 1] {
+            if let Some(col) = (0..WIDTH).find(|&c| squares[row][c].is_some_and(|p| p.kind == PieceType::Pawn)) {
+                return Err(FenError::PawnOnBackRank(Square(row, col)));
+            }
+        }
+
+        let mut board = Board {
+            squares,
+            move_history: Vec::new(),
+            position_counter: HashMap::new(),
+            king_positions,
+            side_to_move,
+            castling_rights,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+            game_over: false,
+            name: "Room".to_string(),
+            variant: match checks {
+                Some(_) => GameVariant::ThreeCheck,
+                None if castling_rights.standard_setup() => GameVariant::Standard,
+                None => GameVariant::Chess960,
+            },
+            checks: checks.unwrap_or_default(),
+            hash: 0,
+            undo_history: Vec::new(),
+        };
+        let waiting = side_to_move.opposite();
+        if Position::from_board(&board, waiting).in_check(waiting) {
+            return Err(FenError::OpponentInCheck);
+        }
+        board.hash = zobrist::hash_position(&board);
+        board.position_counter.insert(board.hash, 1);
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let placement: Vec<String> = (0..HEIGHT).rev()
+            .map(|row| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for col in 0..WIDTH {
+                    match self.squares[row][col] {
+                        None => empty += 1,
+                        Some(p) => {
+                            if empty > 0 {
+                                rank.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            rank.push(p.to_char());
+                        }
+                    }
                 }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect();
+
+        let side = if self.side_to_move == White { "w" } else { "b" };
+        let rights = self.castling_rights;
+        // outside the standard setup the rook files are written out (Shredder-FEN), e.g. "HAha"
+        let letter = |standard: char, file: usize| if rights.standard_setup() { standard } else { (b'A' + file as u8) as char };
+        let castling: String = [
+            (rights.white_king_side, letter('K', rights.king_side_rook_file)),
+            (rights.white_queen_side, letter('Q', rights.queen_side_rook_file)),
+            (rights.black_king_side, letter('K', rights.king_side_rook_file).to_ascii_lowercase()),
+            (rights.black_queen_side, letter('Q', rights.queen_side_rook_file).to_ascii_lowercase()),
+        ].iter()
+            .filter_map(|&(allowed, c)| allowed.then_some(c))
+            .collect();
+        let castling = if castling.is_empty() { "-".to_string() } else { castling };
+        let en_passant = self.en_passant.map_or("-".to_string(), |square| square.to_string());
+
+        let fen = format!("{} {} {} {} {} {}", placement.join("/"), side, castling, en_passant, self.halfmove_clock, self.fullmove_number);
+        if self.variant == GameVariant::ThreeCheck {
+            format!("{} +{}+{}", fen, self.checks.white, self.checks.black)
+        } else {
+            fen
+        }
+    }
+
+    // `None` for squares off the board as well
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.squares.get(square.0).and_then(|row| row.get(square.1)).copied().flatten()
+    }
+
+    // describes moving whatever stands on `from` to `to` without checking that the move is legal,
+    // `promotion` only matters for a pawn reaching the last rank and defaults to a queen
+    pub fn create_move(&self, from: Square, to: Square, promotion: Option<PieceType>) -> Option<Move> {
+        let piece = self.piece_at(from)?;
+        let target = self.piece_at(to);
+        let beside = self.piece_at(Square(from.0, to.1));
+        let kind = match piece.kind {
+            PieceType::King => self.castling_rights.castling_kind(piece, from, to, target).unwrap_or(MoveKind::Normal),
+            PieceType::Pawn if to.0 == 0 || to.0 == HEIGHT - 1 => MoveKind::Promotion(promotion.unwrap_or(PieceType::Queen)),
+            PieceType::Pawn if from.0.abs_diff(to.0) == 2 => MoveKind::DoublePawnPush,
+            PieceType::Pawn if target.is_none() && from.1.abs_diff(to.1) == 1 &&
+                beside.is_some_and(|p| p.kind == PieceType::Pawn && p.color != piece.color) => MoveKind::EnPassant,
+            _ => MoveKind::Normal,
+        };
+        let captured = match kind {
+            MoveKind::EnPassant => beside,
+            MoveKind::CastleKingSide | MoveKind::CastleQueenSide => None,
+            _ => target,
+        };
+        Some(Move { from, to, piece, captured, kind })
+    }
+
+    // like `create_move`, but says why no move could be built
+    pub fn try_create_move(&self, from: Square, to: Square, promotion: Option<PieceType>) -> Result<Move, ChessError> {
+        for square in [from, to] {
+            Square::new(square.0, square.1)?;
+        }
+        self.create_move(from, to, promotion).ok_or(ChessError::EmptySquare(from))
+    }
+
+    // long algebraic notation such as "e2e4" or "e7e8n", the move is not checked for legality
+    pub fn parse_move(&self, text: &str) -> Option<Move> {
+        self.try_parse_move(text).ok()
+    }
+
+    pub fn try_parse_move(&self, text: &str) -> Result<Move, ChessError> {
+        let invalid = || ChessError::InvalidMove(text.to_string());
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return Err(invalid());
+        }
+        let from = Square::parse(&text[..2]).ok_or_else(invalid)?;
+        let to = Square::parse(&text[2..4]).ok_or_else(invalid)?;
+        let promotion = match text[4..].chars().next() {
+            None => None,
+            Some(c) => Some(Piece::from_char(c).map(|p| p.kind).filter(|&k| k != PieceType::King && k != PieceType::Pawn).ok_or_else(invalid)?),
+        };
+        self.try_create_move(from, to, promotion)
+    }
+
+    // checks that `m` is legal for the side to move before playing it, the board is left untouched on error
+    pub fn try_make_move(&mut self, m: Move) -> Result<(), ChessError> {
+        let color = self.side_to_move;
+        for square in [m.from, m.to] {
+            Square::new(square.0, square.1)?;
+        }
+        let piece = self.piece_at(m.from).ok_or(ChessError::EmptySquare(m.from))?;
+        if piece.color != color {
+            return Err(ChessError::WrongColor(m.from));
+        }
+        if let Some(missing) = [White, Black].into_iter().find(|&c| !self.has_king(c)) {
+            return Err(ChessError::MissingKing(missing));
+        }
+        if !moves::allowed_moves(self, m.from, color).contains(&m) {
+            return Err(ChessError::IllegalMove(m));
+        }
+        self.make_move(m);
+        Ok(())
+    }
+
+    // `king_positions` is trusted elsewhere, a board built by hand may not have a king where it says
+    pub fn has_king(&self, color: Color) -> bool {
+        self.king_positions.get(&color)
+            .and_then(|&square| self.piece_at(square))
+            .is_some_and(|piece| piece == Piece::new(color, PieceType::King))
+    }
+
+    pub fn make_move(&mut self, m: Move) {
+        self.move_history.push(m);
+        self.undo_history.push(UndoRecord {
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            checks: self.checks,
+            hash: self.hash,
+        });
+        // take the old side to move, castling rights, en passant and checks out of the hash before they change
+        self.hash ^= zobrist::side_to_move_key(self.side_to_move)
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ zobrist::en_passant_key(self.en_passant_target())
+            ^ zobrist::checks_key(self.checks);
+        self.castling_rights.remove_for_square(m.from);
+        self.castling_rights.remove_for_square(m.to);
+        self.halfmove_clock = if m.piece.kind == PieceType::Pawn || m.is_capture() { 0 } else { self.halfmove_clock + 1 };
+        if m.piece.color == Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = m.piece.color.opposite();
+        self.en_passant = (m.kind == MoveKind::DoublePawnPush).then_some(Square((m.from.0 + m.to.0) / 2, m.from.1));
+
+        if m.kind == MoveKind::EnPassant {
+            self.set_square(m.capture_square(), None);
+        }
+        self.set_square(m.from, None);
+        // in Chess960 the king and rook can land on each other's start squares, so both leave before either is placed
+        if let Some((rook_from, rook_to)) = self.castling_rights.castling_rook(m.kind, m.from.0) {
+            self.set_square(rook_from, None);
+            self.set_square(rook_to, Some(Piece::new(m.piece.color, PieceType::Rook)));
+        }
+        let placed = m.promotion().map_or(m.piece, |kind| Piece::new(m.piece.color, kind));
+        self.set_square(m.destination(), Some(placed));
+        if m.piece.kind == PieceType::King {
+            self.king_positions.insert(m.piece.color, m.destination());
+        }
+        if self.variant == GameVariant::ThreeCheck && self.in_check(self.side_to_move) {
+            *self.checks.given_mut(m.piece.color) += 1;
+        }
+        self.hash ^= zobrist::side_to_move_key(self.side_to_move)
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ zobrist::en_passant_key(self.en_passant_target())
+            ^ zobrist::checks_key(self.checks);
+
+        *self.position_counter.entry(self.hash).or_insert(0) += 1;
+    }
+
+    // takes back the last move played with `make_move`, returns it or `None` if there is nothing to take back
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.undo_history.pop()?;
+        let Some(m) = self.move_history.pop() else {
+            self.undo_history.push(undo);
+            return None;
+        };
+        if let Some(count) = self.position_counter.get_mut(&self.hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.position_counter.remove(&self.hash);
             }
         }
 
-        let val: u32 = *self.position_counter.get(&self.squares).unwrap_or(&0) + 1;
-        if self.max_position_count < val {
-            self.max_position_count = val;
+        self.set_square(m.destination(), None);
+        if let Some((rook_from, rook_to)) = undo.castling_rights.castling_rook(m.kind, m.from.0) {
+            self.set_square(rook_to, None);
+            self.set_square(rook_from, Some(Piece::new(m.piece.color, PieceType::Rook)));
+        }
+        self.set_square(m.from, Some(m.piece));
+        if let Some(captured) = m.captured {
+            self.set_square(m.capture_square(), Some(captured));
         }
-        self.position_counter.insert(self.squares, val);
+        if m.piece.kind == PieceType::King {
+            self.king_positions.insert(m.piece.color, m.from);
+        }
+
+        if m.piece.color == Black {
+            self.fullmove_number -= 1;
+        }
+        self.side_to_move = m.piece.color;
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.checks = undo.checks;
+        self.hash = undo.hash;
+        Some(m)
+    }
+
+    // places (or clears) a square and keeps the hash in sync
+    fn set_square(&mut self, square: Square, piece: Option<Piece>) {
+        if let Some(old) = self.piece_at(square) {
+            self.hash ^= zobrist::piece_key(old, square);
+        }
+        if let Some(new) = piece {
+            self.hash ^= zobrist::piece_key(new, square);
+        }
+        self.squares[square.0][square.1] = piece;
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // the en passant square counts towards the position only if the capture can actually be played,
+    // so each pawn next to the one which has just moved is tried for leaving its king in check
+    pub fn en_passant_target(&self) -> Option<Square> {
+        let target = self.en_passant?;
+        let color = self.side_to_move;
+        let pawn = Piece::new(color, PieceType::Pawn);
+        let captured = step(target, if color == White { -1 } else { 1 }, 0)?;
+        [-1, 1].into_iter()
+            .filter_map(|cols| step(captured, 0, cols))
+            .filter(|&from| self.piece_at(from) == Some(pawn))
+            .any(|from| {
+                let mut squares = self.squares;
+                squares[from.0][from.1] = None;
+                squares[captured.0][captured.1] = None;
+                squares[target.0][target.1] = Some(pawn);
+                self.king_positions.get(&color).is_none_or(|&king| !square_attacked(&squares, king, color.opposite()))
+            })
+            .then_some(target)
+    }
+
+    // a board without that king is never in check
+    pub fn in_check(&self, color: Color) -> bool {
+        self.king_positions.get(&color).is_some_and(|&king| square_attacked(&self.squares, king, color.opposite()))
+    }
+
+    pub fn repetition_count(&self) -> u32 {
+        self.position_counter.get(&self.hash).copied().unwrap_or(0)
+    }
+}
+
+const KNIGHT_STEPS: [(isize, isize); 8] = [(2, 1), (1, 2), (-1, 2), (-2, 1), (-2, -1), (-1, -2), (1, -2), (2, -1)];
+const KING_STEPS: [(isize, isize); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+fn step(square: Square, rows: isize, cols: isize) -> Option<Square> {
+    let row = square.0.checked_add_signed(rows).filter(|&row| row < HEIGHT)?;
+    let col = square.1.checked_add_signed(cols).filter(|&col| col < WIDTH)?;
+    Some(Square(row, col))
+}
+
+// looks outwards from `target` for a piece of `by` attacking it, cheaper than building a `Position` for a single square
+fn square_attacked(squares: &[[Option<Piece>; WIDTH]; HEIGHT], target: Square, by: Color) -> bool {
+    let piece_on = |square: Square| squares[square.0][square.1];
+    let attacker = |square: Option<Square>, kinds: &[PieceType]| {
+        square.and_then(piece_on).is_some_and(|piece| piece.color == by && kinds.contains(&piece.kind))
+    };
+    let pawn_rows = if by == White { -1 } else { 1 };
+    if [-1, 1].into_iter().any(|cols| attacker(step(target, pawn_rows, cols), &[PieceType::Pawn]))
+        || KNIGHT_STEPS.iter().any(|&(rows, cols)| attacker(step(target, rows, cols), &[PieceType::Knight]))
+        || KING_STEPS.iter().any(|&(rows, cols)| attacker(step(target, rows, cols), &[PieceType::King])) {
+        return true;
     }
+    // the first piece along each line decides, the rook and bishop lines are the king's steps repeated
+    KING_STEPS.iter().any(|&(rows, cols)| {
+        let sliders = if rows == 0 || cols == 0 { [PieceType::Rook, PieceType::Queen] } else { [PieceType::Bishop, PieceType::Queen] };
+        let mut square = step(target, rows, cols);
+        while let Some(current) = square {
+            if let Some(piece) = piece_on(current) {
+                return piece.color == by && sliders.contains(&piece.kind);
+            }
+            square = step(current, rows, cols);
+        }
+        false
+    })
 }
 
 pub fn to_string(board: &Board) -> String {
     let mut result: String = String::new();
-    for row in (0 .. HEIGHT) {
+    for row in 0 .. HEIGHT {
         for col in 0 .. 8 {
-            let icon = match &board.squares[row][col] {
-                None => ' ',
-                Some(p) => match p.kind {
-                    PieceType::King => if p.color == Color::White {'K'} else {'k'},
-                    PieceType::Queen => if p.color == Color::White {'Q'} else {'q'},
-                    PieceType::Rook => if p.color == Color::White {'R'} else {'r'},
-                    PieceType::Bishop => if p.color == Color::White {'B'} else {'b'},
-                    PieceType::Knight => if p.color == Color::White {'N'} else {'n'},
-                    PieceType::Pawn => if p.color == Color::White {'P'} else {'p'},
-                }
-            };
+            let icon = board.squares[row][col].map_or(' ', |p| p.to_char());
             result.push(icon);
         }
         result.push('\n');
@@ -133,17 +930,39 @@ impl serde::Serialize for Board {
     }
 }
 
-fn new_pieces(color: Color) -> [Option<Piece>; WIDTH] {
-    [
-        Some(Piece::new(color, PieceType::Rook)),
-        Some(Piece::new(color, PieceType::Knight)),
-        Some(Piece::new(color, PieceType::Bishop)),
-        Some(Piece::new(color, PieceType::Queen)),
-        Some(Piece::new(color, PieceType::King)),
-        Some(Piece::new(color, PieceType::Bishop)),
-        Some(Piece::new(color, PieceType::Knight)),
-        Some(Piece::new(color, PieceType::Rook))
-    ]
+const STANDARD_BACK_RANK: [PieceType; WIDTH] = [
+    PieceType::Rook, PieceType::Knight, PieceType::Bishop, PieceType::Queen,
+    PieceType::King, PieceType::Bishop, PieceType::Knight, PieceType::Rook,
+];
+
+pub const CHESS960_POSITIONS: usize = 960;
+
+// where the two knights go among the five squares left after the bishops and the queen
+const CHESS960_KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+// white's back rank for Chess960 start position `index` in the standard (Scharnagl) numbering, 518 is the usual setup
+pub fn chess960_back_rank(index: usize) -> Option<[PieceType; WIDTH]> {
+    if index >= CHESS960_POSITIONS {
+        return None;
+    }
+    let mut rank = [None; WIDTH];
+    rank[2 * (index % 4) + 1] = Some(PieceType::Bishop);
+    rank[2 * (index / 4 % 4)] = Some(PieceType::Bishop);
+    let empty = |rank: &[Option<PieceType>; WIDTH]| -> Vec<usize> { (0..WIDTH).filter(|&col| rank[col].is_none()).collect() };
+    rank[empty(&rank)[index / 16 % 6]] = Some(PieceType::Queen);
+    let (first, second) = CHESS960_KNIGHTS[index / 96];
+    let free = empty(&rank);
+    rank[free[first]] = Some(PieceType::Knight);
+    rank[free[second]] = Some(PieceType::Knight);
+    // the king always stands between the rooks
+    for (col, kind) in empty(&rank).into_iter().zip([PieceType::Rook, PieceType::King, PieceType::Rook]) {
+        rank[col] = Some(kind);
+    }
+    Some(rank.map(|kind| kind.expect("Every square of the back rank is filled")))
+}
+
+fn new_pieces(color: Color, back_rank: [PieceType; WIDTH]) -> [Option<Piece>; WIDTH] {
+    back_rank.map(|kind| Some(Piece::new(color, kind)))
 }
 
 fn new_pawns(color: Color) -> [Option<Piece>; WIDTH] {
@@ -154,24 +973,314 @@ fn new_empty() -> [Option<Piece>; WIDTH] {
     [None; WIDTH]
 }
 
-pub fn new_board() -> Board {
+fn board_from_back_rank(back_rank: [PieceType; WIDTH]) -> Board {
     let squares = [
-        new_pieces(Color::White),
+        new_pieces(Color::White, back_rank),
         new_pawns(Color::White),
         new_empty(),
         new_empty(),
         new_empty(),
         new_empty(),
         new_pawns(Color::Black),
-        new_pieces(Color::Black)
+        new_pieces(Color::Black, back_rank)
     ];
-    Board {
+    let king_file = back_rank.iter().position(|&kind| kind == PieceType::King).expect("Back rank must have a king");
+    let rook_files: Vec<usize> = (0..WIDTH).filter(|&col| back_rank[col] == PieceType::Rook).collect();
+    let mut board = Board {
         squares,
         move_history: Vec::new(),
-        king_positions: HashMap::from([(Color::White, (0, 4)), (Color::Black, (7, 4))]),
+        king_positions: HashMap::from([(Color::White, Square(0, king_file)), (Color::Black, Square(HEIGHT - 1, king_file))]),
+        side_to_move: White,
+        castling_rights: CastlingRights {
+            king_file,
+            queen_side_rook_file: rook_files[0],
+            king_side_rook_file: rook_files[1],
+            ..CastlingRights::all()
+        },
+        en_passant: None,
+        halfmove_clock: 0,
+        fullmove_number: 1,
         game_over: false,
         name: "Room".to_string(),
-        position_counter: HashMap::from([(squares, 1)]),
-        max_position_count: 1,
+        variant: GameVariant::Standard,
+        checks: Checks::default(),
+        position_counter: HashMap::new(),
+        hash: 0,
+        undo_history: Vec::new(),
+    };
+    board.hash = zobrist::hash_position(&board);
+    board.position_counter.insert(board.hash, 1);
+    board
+}
+
+pub fn new_board() -> Board {
+    board_from_back_rank(STANDARD_BACK_RANK)
+}
+
+// `None` unless `index` is in 0..960
+pub fn new_chess960_board(index: usize) -> Option<Board> {
+    let mut board = board_from_back_rank(chess960_back_rank(index)?);
+    board.variant = GameVariant::Chess960;
+    Some(board)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use crate::board::{chess960_back_rank, new_board, new_chess960_board, Board, CastlingRights, ChessError, FenError, MoveKind, Piece, PieceType, Square, STARTING_FEN, WIDTH};
+    use crate::variant::GameVariant;
+    use crate::board::Color::{Black, White};
+    use crate::moves::{allowed_moves, legal_moves_for};
+    use crate::bitboard::Position;
+
+    #[test]
+    fn test_in_check() {
+        let board = Board::from_fen("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1").unwrap();
+        assert!(board.in_check(White));
+        assert!(!board.in_check(Black));
+        // the rook's line is blocked
+        assert!(!Board::from_fen("4k3/4p3/8/8/8/8/8/4R1K1 b - - 0 1").unwrap().in_check(Black));
+        assert!(Board::from_fen("4k3/3P4/8/8/8/8/8/4K3 b - - 0 1").unwrap().in_check(Black));
+        // every position one move into Kiwipete agrees with the bitboards
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for m in legal_moves_for(&board, White) {
+            let mut after = board.clone();
+            after.make_move(m);
+            for color in [White, Black] {
+                assert_eq!(after.in_check(color), Position::from_board(&after, Black).in_check(color), "{} {:?}", m, color);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        assert_eq!(new_board().to_fen(), STARTING_FEN);
+        assert_eq!(Board::from_fen(STARTING_FEN).unwrap().squares, new_board().squares);
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(Board::from_fen(kiwipete).unwrap().to_fen(), kiwipete);
+
+        let endgame = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 12 40";
+        let board = Board::from_fen(endgame).unwrap();
+        assert_eq!(board.color_to_play(), Black);
+        assert_eq!(board.castling_rights, CastlingRights::default());
+        assert_eq!(board.to_fen(), endgame);
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_fen_after_moves() {
+        let mut board = new_board();
+        board.make_move(board.parse_move("e2e4").unwrap());
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        board.make_move(board.parse_move("g8f6").unwrap());
+        assert_eq!(board.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2");
+        board.make_move(board.parse_move("e1e2").unwrap());
+        assert_eq!(board.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2");
+    }
+
+    #[test]
+    fn test_fen_en_passant() {
+        let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        assert_eq!(board.en_passant, Some(Square(5, 3)));
+        assert!(allowed_moves(&board, Square(4, 4), White).iter().any(|m| m.to == Square(5, 3)));
+
+        let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 2").unwrap();
+        assert!(!allowed_moves(&board, Square(4, 4), White).iter().any(|m| m.to == Square(5, 3)));
+    }
+
+    #[test]
+    fn test_fen_errors() {
+        let invalid = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq", FenError::WrongFieldCount(3)),
+            ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::WrongRankCount(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", FenError::WrongRankLength(1)),
+            ("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::WrongRankLength(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1", FenError::InvalidPiece('X')),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1", FenError::InvalidCastling("KQkx".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1", FenError::InvalidCastling("KQkq".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1", FenError::InvalidCastling("KKkq".to_string())),
+            ("r3k2r/8/8/8/8/8/8/R2K3R w KQkq - 0 1", FenError::UnsupportedCastling("KQkq".to_string())),
+            ("r3k2r/8/8/8/8/8/8/R3K1R1 w GAha - 0 1", FenError::UnsupportedCastling("GAha".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant("e3".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1", FenError::InvalidEnPassant("z9".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", FenError::InvalidHalfmoveClock("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", FenError::InvalidFullmoveNumber("0".to_string())),
+            ("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1", FenError::WrongKingCount(Black, 0)),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", FenError::WrongKingCount(White, 2)),
+            ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank(Square(7, 7))),
+            ("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", FenError::OpponentInCheck),
+        ];
+        for (fen, error) in invalid {
+            assert_eq!(Board::from_fen(fen).unwrap_err(), error, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        // castling, en passant, captures and an underpromotion
+        let moves = [
+            "e2e4", "d7d5", "e4d5", "c7c5", "d5c6", "g8f6", "c6b7", "c8d7",
+            "b7a8n", "b8c6", "g1f3", "d8c7", "f1e2", "e7e6", "e1g1",
+        ];
+        let mut board = new_board();
+        let mut snapshots = Vec::new();
+        for text in moves {
+            snapshots.push(board.clone());
+            board.make_move(board.parse_move(text).unwrap());
+        }
+        assert_eq!(board.to_fen(), "N3kb1r/p1qb1ppp/2n1pn2/8/8/5N2/PPPPBPPP/RNBQ1RK1 b k - 1 8");
+
+        while let Some(before) = snapshots.pop() {
+            let taken_back = board.unmake_move().unwrap();
+            assert_eq!(taken_back.to_string(), moves[snapshots.len()]);
+            assert_eq!(board.to_fen(), before.to_fen());
+            assert_eq!(board.squares, before.squares);
+            assert_eq!(board.king_positions, before.king_positions);
+            assert_eq!(board.hash(), before.hash());
+            assert_eq!(board.position_counter, before.position_counter);
+            assert_eq!(board.move_history, before.move_history);
+        }
+        assert_eq!(board.unmake_move(), None);
+
+        // the repetition count goes back down with the moves taken back
+        let mut board = new_board();
+        for _ in 0..2 {
+            for text in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                board.make_move(board.parse_move(text).unwrap());
+            }
+        }
+        assert_eq!(board.repetition_count(), 3);
+        board.unmake_move();
+        board.unmake_move();
+        board.unmake_move();
+        board.unmake_move();
+        assert_eq!(board.repetition_count(), 2);
+    }
+
+    #[test]
+    fn test_square_and_move_notation() {
+        assert_eq!(Square::parse("e4"), Some(Square(3, 4)));
+        assert_eq!(Square(7, 0).to_string(), "a8");
+        assert_eq!(Square::parse("i1"), None);
+        assert_eq!(Square::parse("e9"), None);
+
+        let board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let black_pawn = Piece { color: Black, kind: PieceType::Pawn };
+        let black_rook = Piece { color: Black, kind: PieceType::Rook };
+        let expected = [
+            ("e4e5", None, None),
+            ("e5d6", Some(MoveKind::EnPassant), Some(black_pawn)),
+            ("e5e6", Some(MoveKind::Normal), None),
+            ("e1g1", Some(MoveKind::CastleKingSide), None),
+            ("e1c1", Some(MoveKind::CastleQueenSide), None),
+            ("e1h1", Some(MoveKind::CastleKingSide), None),
+            ("b7a8n", Some(MoveKind::Promotion(PieceType::Knight)), Some(black_rook)),
+            ("b7b8", Some(MoveKind::Promotion(PieceType::Queen)), None),
+            ("a1a8", Some(MoveKind::Normal), Some(black_rook)),
+        ];
+        for (text, kind, captured) in expected {
+            let parsed = board.parse_move(text);
+            assert_eq!(parsed.map(|m| m.kind), kind, "{}", text);
+            assert_eq!(parsed.and_then(|m| m.captured), captured, "{}", text);
+        }
+        assert_eq!(board.parse_move("b7a8n").unwrap().to_string(), "b7a8n");
+        assert_eq!(board.parse_move("b7b8").unwrap().to_string(), "b7b8q");
+        let castling = board.parse_move("e1g1").unwrap();
+        assert_eq!(board.castling_rights.castling_rook(castling.kind, 0), Some((Square(0, 7), Square(0, 5))));
+        assert_eq!(board.parse_move("e1h1").unwrap().destination(), Square(0, 6));
+        assert_eq!(board.parse_move("e5d6").unwrap().capture_square(), Square(4, 3));
+        for invalid in ["", "e5", "e5d6k", "e5d6x", "z5d6", "e5d66"] {
+            assert_eq!(board.parse_move(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_try_make_move() {
+        let mut board = new_board();
+        let e4 = board.try_parse_move("e2e4").unwrap();
+        assert_eq!(board.try_parse_move("e2e9"), Err(ChessError::InvalidMove("e2e9".to_string())));
+        assert_eq!(board.try_parse_move("e7e8k"), Err(ChessError::InvalidMove("e7e8k".to_string())));
+        assert_eq!(board.try_parse_move("e3e4"), Err(ChessError::EmptySquare(Square(2, 4))));
+        assert_eq!(board.try_create_move(Square(1, 4), Square(1, 9), None), Err(ChessError::OffBoard(1, 9)));
+
+        let e5 = board.parse_move("e7e5").unwrap();
+        assert_eq!(board.try_make_move(e5), Err(ChessError::WrongColor(Square(6, 4))));
+        let e2e5 = board.parse_move("e2e5").unwrap();
+        assert_eq!(board.try_make_move(e2e5), Err(ChessError::IllegalMove(e2e5)));
+        assert!(board.move_history.is_empty());
+        assert_eq!(board.try_make_move(e4), Ok(()));
+        assert_eq!(board.try_make_move(e5), Ok(()));
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        assert_eq!(board.piece_at(Square(9, 9)), None);
+
+        board.squares[7][4] = None;
+        let nf3 = board.parse_move("g1f3").unwrap();
+        assert_eq!(board.try_make_move(nf3), Err(ChessError::MissingKing(Black)));
+        assert_eq!(Square::new(7, 7), Ok(Square(7, 7)));
+        assert_eq!(ChessError::from(Board::from_fen("8/8 w - -").unwrap_err()).to_string(), "invalid FEN: expected 8 ranks, found 2");
+    }
+
+    #[test]
+    fn test_chess960_start_positions() {
+        let standard = new_chess960_board(518).unwrap();
+        assert_eq!(standard.squares, new_board().squares);
+        assert_eq!(standard.to_fen(), STARTING_FEN);
+        assert_eq!(new_chess960_board(0).unwrap().to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+        assert_eq!(new_chess960_board(959).unwrap().to_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1");
+        assert!(new_chess960_board(960).is_none());
+
+        let mut seen = HashSet::new();
+        for index in 0..960 {
+            let rank = chess960_back_rank(index).unwrap();
+            let files = |kind: PieceType| -> Vec<usize> { (0..WIDTH).filter(|&col| rank[col] == kind).collect() };
+            let (bishops, rooks, king) = (files(PieceType::Bishop), files(PieceType::Rook), files(PieceType::King)[0]);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}", index);
+            assert!(rooks[0] < king && king < rooks[1], "{}", index);
+            assert!(seen.insert(rank), "{}", index);
+
+            let board = new_chess960_board(index).unwrap();
+            assert_eq!(Board::from_fen(&board.to_fen()).unwrap().hash(), board.hash(), "{}", index);
+        }
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        // king and rook swap squares
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/5KR1 w G - 0 1").unwrap();
+        assert_eq!(board.variant, GameVariant::Chess960);
+        let castling = board.parse_move("f1g1").unwrap();
+        assert_eq!(castling.kind, MoveKind::CastleKingSide);
+        assert_eq!(castling.captured, None);
+        assert!(allowed_moves(&board, Square(0, 5), White).contains(&castling));
+        board.make_move(castling);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        assert_eq!(board.king_positions[&White], Square(0, 6));
+        assert_eq!(board.hash(), Board::from_fen(&board.to_fen()).unwrap().hash());
+        board.unmake_move();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/5KR1 w G - 0 1");
+
+        // the king stays where it is and only the rook moves
+        let mut board = Board::from_fen("1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1").unwrap();
+        board.make_move(board.parse_move("g1b1").unwrap());
+        assert_eq!(board.to_fen(), "1r4kr/8/8/8/8/8/8/2KR3R b hb - 1 1");
+        board.make_move(board.parse_move("g8h8").unwrap());
+        assert_eq!(board.to_fen(), "1r3rk1/8/8/8/8/8/8/2KR3R w - - 2 2");
+
+        // a king two squares away from the rook does not castle by stepping two squares
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").unwrap();
+        assert_eq!(board.parse_move("c1a1").unwrap().kind, MoveKind::Normal);
+        assert_eq!(board.parse_move("c1b1").unwrap().kind, MoveKind::CastleQueenSide);
+
+        // a piece standing where the king or the rook has to go blocks castling
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RK1B4 w A - 0 1").unwrap();
+        assert!(!allowed_moves(&board, Square(0, 1), White).iter().any(|m| m.is_castling()));
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RKB5 w A - 0 1").unwrap();
+        assert!(!allowed_moves(&board, Square(0, 1), White).iter().any(|m| m.is_castling()));
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        assert!(allowed_moves(&board, Square(0, 1), White).iter().any(|m| m.is_castling()));
     }
 }
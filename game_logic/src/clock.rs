@@ -0,0 +1,200 @@
+use std::time::{Duration, Instant};
+use crate::board::Color;
+
+const MAX_BASE_MS: u64 = 3 * 60 * 60 * 1000;
+const MAX_INCREMENT_MS: u64 = 60 * 1000;
+
+// how a player's clock is topped up after every move
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum IncrementMode {
+    // the whole increment is added, so the clock can grow
+    #[default]
+    Fischer,
+    // the time spent on the move is given back, up to the increment
+    Bronstein,
+}
+
+// chosen when a room is created, times in milliseconds
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TimeControl {
+    pub base: u64,
+    #[serde(default)]
+    pub increment: u64,
+    #[serde(default)]
+    pub mode: IncrementMode,
+}
+
+impl TimeControl {
+    // `None` for a control no game could be played with, or one too long to be sensible
+    pub fn checked(self) -> Option<TimeControl> {
+        ((1..=MAX_BASE_MS).contains(&self.base) && self.increment <= MAX_INCREMENT_MS).then_some(self)
+    }
+}
+
+// the clocks as sent to the players in milliseconds, `running` is the side whose time is going down
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ClockState {
+    pub white: u64,
+    pub black: u64,
+    pub running: Option<Color>,
+}
+
+// the server's clocks for one game, every method takes the current time so the clock can be tested without waiting
+#[derive(Debug, Clone)]
+pub struct Clock {
+    pub control: TimeControl,
+    white: Duration,
+    black: Duration,
+    // whose clock is running and since when
+    running: Option<(Color, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        let base = Duration::from_millis(control.base);
+        Clock { control, white: base, black: base, running: None }
+    }
+
+    fn time_mut(&mut self, color: Color) -> &mut Duration {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    pub fn start(&mut self, color: Color, now: Instant) {
+        self.stop(now);
+        self.running = Some((color, now));
+    }
+
+    // charges the running side for the time used so far and stops the clock
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, since)) = self.running.take() {
+            let used = now.saturating_duration_since(since);
+            let time = self.time_mut(color);
+            *time = time.saturating_sub(used);
+        }
+    }
+
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let time = match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        };
+        match self.running {
+            Some((running, since)) if running == color => time.saturating_sub(now.saturating_duration_since(since)),
+            _ => time,
+        }
+    }
+
+    // called once the running side has moved: stops its clock, tops it up and starts the opponent's,
+    // a side whose time had already run out gets nothing back
+    pub fn press(&mut self, now: Instant) {
+        let Some((color, since)) = self.running else {
+            return;
+        };
+        let used = now.saturating_duration_since(since);
+        self.stop(now);
+        let increment = Duration::from_millis(self.control.increment);
+        let bonus = match self.control.mode {
+            IncrementMode::Fischer => increment,
+            IncrementMode::Bronstein => increment.min(used),
+        };
+        let time = self.time_mut(color);
+        if !time.is_zero() {
+            *time += bonus;
+        }
+        self.running = Some((color.opposite(), now));
+    }
+
+    // the side which has run out of time, only the running clock can fall
+    pub fn flagged(&self, now: Instant) -> Option<Color> {
+        self.running.map(|(color, _)| color).filter(|&color| self.remaining(color, now).is_zero())
+    }
+
+    // how long until the running side's flag falls
+    pub fn time_to_flag(&self, now: Instant) -> Option<Duration> {
+        self.running.map(|(color, _)| self.remaining(color, now))
+    }
+
+    pub fn state(&self, now: Instant) -> ClockState {
+        ClockState {
+            white: self.remaining(Color::White, now).as_millis() as u64,
+            black: self.remaining(Color::Black, now).as_millis() as u64,
+            running: self.running.map(|(color, _)| color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+    use crate::board::Color;
+    use crate::clock::{Clock, ClockState, IncrementMode, TimeControl};
+
+    fn seconds(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_fischer_increment() {
+        let control = TimeControl { base: 60_000, increment: 2_000, mode: IncrementMode::Fischer };
+        let mut clock = Clock::new(control);
+        let t0 = Instant::now();
+        assert_eq!(clock.time_to_flag(t0), None);
+        clock.start(Color::White, t0);
+        assert_eq!(clock.remaining(Color::White, t0 + seconds(10)), seconds(50));
+        assert_eq!(clock.remaining(Color::Black, t0 + seconds(10)), seconds(60));
+
+        clock.press(t0 + seconds(10));
+        assert_eq!(clock.remaining(Color::White, t0 + seconds(20)), seconds(52));
+        assert_eq!(clock.remaining(Color::Black, t0 + seconds(20)), seconds(50));
+
+        // a quick move still earns the whole increment
+        clock.press(t0 + seconds(11));
+        assert_eq!(clock.state(t0 + seconds(11)), ClockState { white: 52_000, black: 61_000, running: Some(Color::White) });
+
+        clock.stop(t0 + seconds(12));
+        assert_eq!(clock.state(t0 + seconds(100)), ClockState { white: 51_000, black: 61_000, running: None });
+    }
+
+    #[test]
+    fn test_bronstein_delay() {
+        let control = TimeControl { base: 60_000, increment: 5_000, mode: IncrementMode::Bronstein };
+        let mut clock = Clock::new(control);
+        let t0 = Instant::now();
+        clock.start(Color::White, t0);
+        // a move within the delay costs nothing
+        clock.press(t0 + seconds(3));
+        assert_eq!(clock.remaining(Color::White, t0 + seconds(3)), seconds(60));
+        // a longer one is only partly given back
+        clock.press(t0 + seconds(13));
+        assert_eq!(clock.remaining(Color::Black, t0 + seconds(13)), seconds(55));
+    }
+
+    #[test]
+    fn test_flag_fall() {
+        let control = TimeControl { base: 1_000, increment: 1_000, mode: IncrementMode::Fischer };
+        let mut clock = Clock::new(control);
+        let t0 = Instant::now();
+        clock.start(Color::White, t0);
+        assert_eq!(clock.flagged(t0 + Duration::from_millis(999)), None);
+        assert_eq!(clock.time_to_flag(t0 + Duration::from_millis(400)), Some(Duration::from_millis(600)));
+        assert_eq!(clock.flagged(t0 + seconds(1)), Some(Color::White));
+
+        // moving after the flag fell does not bring the time back
+        clock.press(t0 + seconds(2));
+        assert_eq!(clock.remaining(Color::White, t0 + seconds(2)), Duration::ZERO);
+        assert_eq!(clock.flagged(t0 + seconds(2)), None);
+        assert_eq!(clock.flagged(t0 + seconds(3)), Some(Color::Black));
+    }
+
+    #[test]
+    fn test_checked_time_control() {
+        let control: TimeControl = serde_json::from_str(r#"{"base":180000,"increment":2000}"#).unwrap();
+        assert_eq!(control.mode, IncrementMode::Fischer);
+        assert_eq!(control.checked(), Some(control));
+        assert_eq!(TimeControl { base: 0, ..control }.checked(), None);
+        assert_eq!(TimeControl { increment: 3_600_000, ..control }.checked(), None);
+    }
+}
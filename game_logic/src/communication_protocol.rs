@@ -1,18 +1,36 @@
 use std::collections::HashSet;
-use crate::board::Color;
+use crate::board::{Color, GameStatus, PieceType, Square};
+use crate::clock::{ClockState, TimeControl};
+use crate::uci_engine::EngineSettings;
+use crate::variant::GameVariant;
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum MsgType {
-    Join, Create, Move, Possible, Rematch, Ping
+    Join, Create, Move, Possible, Rematch, Ping, ClaimDraw, Spectate,
+    Resign, OfferDraw, AcceptDraw, DeclineDraw, RequestTakeback, AcceptTakeback, DeclineTakeback
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct JsonMsg {
     pub msg_type: MsgType,
     pub room_id: u32,
-    pub make_move: Option<((usize, usize), (usize, usize))>,
-    pub possible_moves: Option<(usize, usize)>,
+    pub make_move: Option<(Square, Square)>,
+    // piece chosen when a pawn reaches the last rank, clients which do not send it promote to a queen
+    #[serde(default)]
+    pub promotion: Option<PieceType>,
+    pub possible_moves: Option<Square>,
     pub room_name: Option<String>,
+    #[serde(default)]
+    pub variant: GameVariant,
+    // Chess960 start position (0-959), a random one is picked when it is missing
+    #[serde(default)]
+    pub start_position: Option<usize>,
+    // set on Create for a game against the server's engine instead of another player
+    #[serde(default)]
+    pub engine: Option<EngineSettings>,
+    // set on Create for a game with clocks, rooms without one are untimed
+    #[serde(default)]
+    pub time_control: Option<TimeControl>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -30,14 +48,125 @@ pub struct JsonMsgServer {
     pub board: Option<String>,
     pub room_id: Option<u32>,
     pub color: Option<Color>,
-    pub possible_moves: HashSet<(usize, usize)>,
+    pub possible_moves: HashSet<Square>,
+    // how the game ended, sent along with the GameResult messages
+    #[serde(default)]
+    pub result: Option<GameStatus>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub enum ServerMsg {
-    Board{current_board: String, last_move: Option<((usize, usize), (usize, usize))>, in_check: Option<(usize, usize)>},
+    Board{current_board: String, last_move: Option<(Square, Square)>, last_move_san: Option<String>, in_check: Option<Square>, #[serde(default)] clock: Option<ClockState>},
     Rematch{my_offer: bool},
-    Rooms{room_names: Vec<(u32, String)>},
+    // rooms waiting for a second player, and games in progress with their spectator count
+    Rooms{room_names: Vec<(u32, String)>, #[serde(default)] games: Vec<(u32, String, usize)>},
+    // sent before the game so far is replayed to a new spectator, and again when a rematch starts
+    Spectating{room_id: u32, name: String},
     Disconnected,
     PlayersOnline{count: usize},
+    // offers are sent to both players, a Board update means any pending offer was cancelled by a move
+    DrawOffer{my_offer: bool},
+    DrawDeclined,
+    TakebackRequest{my_request: bool},
+    TakebackDeclined,
+    // the last `plies` boards are taken back
+    Takeback{plies: usize, in_check: Option<Square>, clock: Option<ClockState>},
+    // a Create the server turned down, `reason` is shown to the player
+    RoomRefused{reason: String},
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use crate::board::{Color, DrawReason, GameStatus, PieceType, Square, WinReason};
+    use crate::clock::{ClockState, IncrementMode, TimeControl};
+    use crate::communication_protocol::{JsonMsg, JsonMsgServer, MsgType, MsgTypeServer, ServerMsg};
+    use crate::uci_engine::EngineSettings;
+    use crate::variant::GameVariant;
+
+    #[test]
+    fn test_squares_keep_pair_format() {
+        let text = r#"{"msg_type":"Move","room_id":7,"make_move":[[1,4],[3,4]],"possible_moves":null,"room_name":null}"#;
+        let msg: JsonMsg = serde_json::from_str(text).unwrap();
+        assert!(matches!(msg.msg_type, MsgType::Move));
+        assert_eq!(msg.make_move, Some((Square(1, 4), Square(3, 4))));
+        assert_eq!(msg.promotion, None);
+
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Possible","room_id":7,"make_move":null,"promotion":"Knight","possible_moves":[6,0],"room_name":null}"#).unwrap();
+        assert_eq!(msg.possible_moves, Some(Square(6, 0)));
+        assert_eq!(msg.promotion, Some(PieceType::Knight));
+
+        let board = ServerMsg::Board { current_board: String::new(), last_move: Some((Square(1, 4), Square(3, 4))), last_move_san: None, in_check: Some(Square(7, 4)), clock: None };
+        assert_eq!(serde_json::to_string(&board).unwrap(),
+                   r#"{"Board":{"current_board":"","last_move":[[1,4],[3,4]],"last_move_san":null,"in_check":[7,4],"clock":null}}"#);
+    }
+
+    #[test]
+    fn test_variant_message() {
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"plain"}"#).unwrap();
+        assert_eq!(msg.variant, GameVariant::Standard);
+
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"960","variant":"Chess960","start_position":518}"#).unwrap();
+        assert_eq!(msg.variant, GameVariant::Chess960);
+        assert_eq!(msg.start_position, Some(518));
+    }
+
+    #[test]
+    fn test_engine_message() {
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"human"}"#).unwrap();
+        assert_eq!(msg.engine, None);
+
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"bot","engine":{"skill":5}}"#).unwrap();
+        assert_eq!(msg.engine, Some(EngineSettings { skill: Some(5), movetime: None }));
+
+        let refused = ServerMsg::RoomRefused { reason: "The computer cannot play ThreeCheck".to_string() };
+        assert_eq!(serde_json::to_string(&refused).unwrap(), r#"{"RoomRefused":{"reason":"The computer cannot play ThreeCheck"}}"#);
+    }
+
+    #[test]
+    fn test_time_control_message() {
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"untimed"}"#).unwrap();
+        assert_eq!(msg.time_control, None);
+
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"Create","room_id":0,"room_name":"blitz","time_control":{"base":180000,"increment":2000,"mode":"Bronstein"}}"#).unwrap();
+        assert_eq!(msg.time_control, Some(TimeControl { base: 180_000, increment: 2_000, mode: IncrementMode::Bronstein }));
+
+        let clock = ClockState { white: 60_000, black: 59_500, running: Some(Color::Black) };
+        let board = ServerMsg::Board { current_board: String::new(), last_move: None, last_move_san: None, in_check: None, clock: Some(clock) };
+        assert!(serde_json::to_string(&board).unwrap().ends_with(r#""clock":{"white":60000,"black":59500,"running":"Black"}}}"#));
+    }
+
+    #[test]
+    fn test_offer_messages() {
+        let msg: JsonMsg = serde_json::from_str(r#"{"msg_type":"AcceptTakeback","room_id":7,"make_move":null,"possible_moves":null,"room_name":null}"#).unwrap();
+        assert!(matches!(msg.msg_type, MsgType::AcceptTakeback));
+        assert_eq!(serde_json::to_string(&ServerMsg::DrawOffer { my_offer: false }).unwrap(), r#"{"DrawOffer":{"my_offer":false}}"#);
+        assert_eq!(serde_json::to_string(&ServerMsg::TakebackDeclined).unwrap(), r#""TakebackDeclined""#);
+        let takeback = ServerMsg::Takeback { plies: 2, in_check: None, clock: None };
+        assert_eq!(serde_json::to_string(&takeback).unwrap(), r#"{"Takeback":{"plies":2,"in_check":null,"clock":null}}"#);
+    }
+
+    #[test]
+    fn test_rooms_with_games() {
+        let rooms = ServerMsg::Rooms { room_names: vec![(1, "open".to_string())], games: vec![(2, "live".to_string(), 3)] };
+        assert_eq!(serde_json::to_string(&rooms).unwrap(), r#"{"Rooms":{"room_names":[[1,"open"]],"games":[[2,"live",3]]}}"#);
+        // older clients and servers know nothing of the games list
+        let rooms: ServerMsg = serde_json::from_str(r#"{"Rooms":{"room_names":[]}}"#).unwrap();
+        assert!(matches!(rooms, ServerMsg::Rooms { games, .. } if games.is_empty()));
+    }
+
+    #[test]
+    fn test_game_result_reason() {
+        let msg = JsonMsgServer { msg_type: MsgTypeServer::GameResultBlackWon, board: None, room_id: None, color: None, possible_moves: HashSet::new(),
+            result: Some(GameStatus::Win(Color::Black, WinReason::Checkmate)) };
+        assert_eq!(serde_json::to_string(&msg).unwrap(),
+                   r#"{"msg_type":"GameResultBlackWon","board":null,"room_id":null,"color":null,"possible_moves":[],"result":{"Win":["Black","Checkmate"]}}"#);
+
+        let msg = JsonMsgServer { result: Some(GameStatus::Draw(DrawReason::InsufficientMaterial)), ..msg };
+        assert!(serde_json::to_string(&msg).unwrap().ends_with(r#""result":{"Draw":"InsufficientMaterial"}}"#));
+
+        // messages from older servers come without a reason
+        let msg: JsonMsgServer = serde_json::from_str(r#"{"msg_type":"GameResultDraw","board":null,"room_id":null,"color":null,"possible_moves":[]}"#).unwrap();
+        assert_eq!(msg.result, None);
+    }
 }
@@ -0,0 +1,283 @@
+use crate::bitboard::{squares_of, Bitboard, Position, KING_ATTACKS};
+use crate::board::{Board, Color, Piece, PieceType, HEIGHT, WIDTH};
+use crate::board::Color::{Black, White};
+
+// all scores are in centipawns
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 320;
+pub const BISHOP_VALUE: i32 = 330;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
+// 24 with every knight, bishop, rook and queen on the board, falls towards 0 as they come off
+const MAX_PHASE: i32 = 24;
+
+const MOBILITY_WEIGHTS: [(PieceType, i32); 4] = [(PieceType::Knight, 4), (PieceType::Bishop, 5), (PieceType::Rook, 2), (PieceType::Queen, 1)];
+
+const PAWN_SHIELD_BONUS: i32 = 10;
+const KING_ZONE_ATTACK_PENALTY: i32 = 10;
+const DOUBLED_PAWN_PENALTY: i32 = 15;
+const ISOLATED_PAWN_PENALTY: i32 = 15;
+// indexed by how many ranks the pawn has advanced
+const PASSED_PAWN_BONUS: [i32; HEIGHT] = [0, 10, 15, 25, 40, 60, 90, 0];
+
+const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+
+// piece-square tables from white's point of view, written with rank 8 on top so they read like a board
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+// the king hides behind its pawns while there are pieces around...
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// ...and walks to the centre once they are gone
+const KING_ENDGAME_TABLE: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+pub fn piece_value(kind: PieceType) -> i32 {
+    match kind {
+        PieceType::Pawn => PAWN_VALUE,
+        PieceType::Knight => KNIGHT_VALUE,
+        PieceType::Bishop => BISHOP_VALUE,
+        PieceType::Rook => ROOK_VALUE,
+        PieceType::Queen => QUEEN_VALUE,
+        PieceType::King => 0,
+    }
+}
+
+// black reads the white tables upside down
+fn table_index(color: Color, square: u8) -> usize {
+    let (row, col) = (square as usize / WIDTH, square as usize % WIDTH);
+    let row = if color == White { HEIGHT - 1 - row } else { row };
+    row * WIDTH + col
+}
+
+// how many ranks a piece of `color` on `square` has moved up the board
+fn relative_rank(color: Color, square: u8) -> usize {
+    let row = square as usize / WIDTH;
+    if color == White { row } else { HEIGHT - 1 - row }
+}
+
+fn game_phase(position: &Position) -> i32 {
+    let phase: i32 = [(PieceType::Knight, 1), (PieceType::Bishop, 1), (PieceType::Rook, 2), (PieceType::Queen, 4)].iter()
+        .map(|&(kind, weight)| weight * (position.pieces(White, kind) | position.pieces(Black, kind)).count_ones() as i32)
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+fn material_and_tables(position: &Position, color: Color, phase: i32) -> i32 {
+    let table_score = |kind: PieceType, table: &[i32; 64]| -> i32 {
+        squares_of(position.pieces(color, kind))
+            .map(|square| piece_value(kind) + table[table_index(color, square)])
+            .sum()
+    };
+    let king = position.king_square(color).map_or(0, |square| {
+        let index = table_index(color, square);
+        (KING_MIDDLEGAME_TABLE[index] * phase + KING_ENDGAME_TABLE[index] * (MAX_PHASE - phase)) / MAX_PHASE
+    });
+    table_score(PieceType::Pawn, &PAWN_TABLE)
+        + table_score(PieceType::Knight, &KNIGHT_TABLE)
+        + table_score(PieceType::Bishop, &BISHOP_TABLE)
+        + table_score(PieceType::Rook, &ROOK_TABLE)
+        + table_score(PieceType::Queen, &QUEEN_TABLE)
+        + king
+}
+
+// squares a piece could move to, captures included
+fn mobility(position: &Position, color: Color) -> i32 {
+    let own = position.occupied_by(color);
+    MOBILITY_WEIGHTS.iter()
+        .flat_map(|&(kind, weight)| squares_of(position.pieces(color, kind)).map(move |square| (kind, weight, square)))
+        .map(|(kind, weight, square)| weight * (position.piece_attacks(Piece { color, kind }, square) & !own).count_ones() as i32)
+        .sum()
+}
+
+// pawns in front of the king and enemy attacks next to it, only matters while there are pieces left to attack with
+fn king_safety(position: &Position, color: Color, phase: i32) -> i32 {
+    let Some(king) = position.king_square(color) else {
+        return 0;
+    };
+    let (row, col) = (king as usize / WIDTH, king as usize % WIDTH);
+    let shield = squares_of(position.pieces(color, PieceType::Pawn))
+        .filter(|&pawn| {
+            let (pawn_row, pawn_col) = (pawn as usize / WIDTH, pawn as usize % WIDTH);
+            let ahead = if color == White { pawn_row.checked_sub(row) } else { row.checked_sub(pawn_row) };
+            pawn_col.abs_diff(col) <= 1 && matches!(ahead, Some(1) | Some(2))
+        })
+        .count()
+        .min(3) as i32;
+    let attacked = (KING_ATTACKS[king as usize] & position.attacks(color.opposite())).count_ones() as i32;
+    (shield * PAWN_SHIELD_BONUS - attacked * KING_ZONE_ATTACK_PENALTY) * phase / MAX_PHASE
+}
+
+fn pawn_structure(position: &Position, color: Color) -> i32 {
+    let pawns = position.pieces(color, PieceType::Pawn);
+    let enemy_pawns = position.pieces(color.opposite(), PieceType::Pawn);
+    let file = |col: usize| FILE_A << col;
+    let neighbour_files = |col: usize| (if col > 0 { file(col - 1) } else { 0 }) | (if col < WIDTH - 1 { file(col + 1) } else { 0 });
+
+    let doubled: i32 = (0..WIDTH)
+        .map(|col| ((pawns & file(col)).count_ones() as i32 - 1).max(0))
+        .sum();
+    squares_of(pawns)
+        .map(|pawn| {
+            let (row, col) = (pawn as usize / WIDTH, pawn as usize % WIDTH);
+            let ahead = if color == White { u64::MAX.checked_shl((row as u32 + 1) * WIDTH as u32).unwrap_or(0) } else { (1 << (row * WIDTH)) - 1 };
+            let isolated = if pawns & neighbour_files(col) == 0 { -ISOLATED_PAWN_PENALTY } else { 0 };
+            let passed = if enemy_pawns & ahead & (file(col) | neighbour_files(col)) == 0 {
+                PASSED_PAWN_BONUS[relative_rank(color, pawn)]
+            } else {
+                0
+            };
+            isolated + passed
+        })
+        .sum::<i32>()
+        - doubled * DOUBLED_PAWN_PENALTY
+}
+
+fn side_score(position: &Position, color: Color, phase: i32) -> i32 {
+    material_and_tables(position, color, phase)
+        + mobility(position, color)
+        + king_safety(position, color, phase)
+        + pawn_structure(position, color)
+}
+
+// positive when the side to move is better
+pub fn evaluate_position(position: &Position) -> i32 {
+    let phase = game_phase(position);
+    let score = side_score(position, White, phase) - side_score(position, Black, phase);
+    if position.side_to_move == White { score } else { -score }
+}
+
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_position(&Position::from_board(board, board.color_to_play()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::Position;
+    use crate::board::{new_board, Board, PieceType};
+    use crate::eval::{evaluate, pawn_structure, piece_value, ISOLATED_PAWN_PENALTY, PASSED_PAWN_BONUS};
+    use crate::board::Color::{Black, White};
+
+    fn eval_fen(fen: &str) -> i32 {
+        evaluate(&Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn test_symmetric_positions() {
+        assert_eq!(evaluate(&new_board()), 0);
+        // the same position with the colours swapped scores the same for the side to move
+        assert_eq!(eval_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"),
+                   eval_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3"));
+        let white_to_move = eval_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert!(white_to_move > 0);
+        assert_eq!(eval_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"), -white_to_move);
+    }
+
+    #[test]
+    fn test_material() {
+        // taking black's queen off the board is worth about a queen
+        let queen_up = eval_fen("rnb1kbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+            - eval_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        assert!((piece_value(PieceType::Queen) - 100..piece_value(PieceType::Queen) + 100).contains(&queen_up));
+        assert!(eval_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1") > 400);
+        assert!(eval_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1") < -400);
+    }
+
+    #[test]
+    fn test_piece_placement() {
+        // a knight in the centre beats one in the corner, a centralised king is worth more in the endgame
+        assert!(eval_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1") > eval_fen("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"));
+        assert!(eval_fen("4k3/8/8/8/3K4/8/8/8 w - - 0 1") > eval_fen("4k3/8/8/8/8/8/8/K7 w - - 0 1"));
+        // with queens on, a king left in the middle behind no pawns is worse than a castled one
+        let castled = eval_fen("rnbq1rk1/ppppbppp/5n2/4p3/4P3/5N2/PPPPBPPP/RNBQ1RK1 w - - 6 5");
+        let exposed = eval_fen("rnbq1rk1/ppppbppp/5n2/4p3/4P3/3K1N2/PPPPBPPP/RNBQ3R w - - 6 5");
+        assert!(castled > exposed);
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        let position = |fen: &str| Position::from_board(&Board::from_fen(fen).unwrap(), White);
+        // a lone pawn is isolated but passed
+        let lone = position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert_eq!(pawn_structure(&lone, White), PASSED_PAWN_BONUS[1] - ISOLATED_PAWN_PENALTY);
+        assert_eq!(pawn_structure(&lone, Black), 0);
+        // doubled and blocked by the pawn in front of them
+        let doubled = position("4k3/4p3/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        assert!(pawn_structure(&doubled, White) < pawn_structure(&position("4k3/4p3/8/8/8/3P4/4P3/4K3 w - - 0 1"), White));
+        // the further a passed pawn has run, the more it is worth
+        assert!(eval_fen("4k3/8/1P6/8/8/8/8/4K3 w - - 0 1") > eval_fen("4k3/8/8/8/8/1P6/8/4K3 w - - 0 1"));
+        assert!(pawn_structure(&position("4k3/8/8/8/8/1P6/8/4K3 w - - 0 1"), White) >
+            pawn_structure(&position("4k3/p7/8/8/8/1P6/8/4K3 w - - 0 1"), White));
+    }
+}
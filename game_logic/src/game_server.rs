@@ -1,15 +1,25 @@
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::net::TcpStream;
-use std::sync::mpsc::Receiver;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
 use rand::random;
 use tungstenite::protocol::Role;
 use tungstenite::WebSocket;
-use crate::{BoardsType, broadcast_rooms_message, send_board_update, send_new_room, send_possible_moves, send_game_over, send_rematch_offer, send_opponent_disconnect, broadcast_players_online};
+use crate::{BoardsType, ClientsType, SpectatorsType, broadcast_rooms_message, send_board_update, send_new_room, send_possible_moves, send_game_over, send_rematch_offer, send_opponent_disconnect, broadcast_players_online, send_draw_offer, send_takeback_request, send_offer_declined, send_takeback, send_spectating, send_room_refused};
+use crate::bitboard::Position;
 use crate::board::Color::{Black, White};
-use crate::board::{new_board, Board, Color, GameStatus, Piece, PieceType};
-use crate::board::PieceType::{King, Pawn};
+use crate::board::{new_chess960_board, Board, Color, DrawReason, GameStatus, Move, Square, WinReason};
+use crate::board::PieceType::Queen;
+use crate::clock::{Clock, ClockState};
 use crate::communication_protocol::{JsonMsg, MsgType};
-use crate::moves::{all_potential_attacks, all_potential_moves, allowed_moves, game_result};
+use crate::moves::{allowed_moves, claimable_draw, has_mating_material, to_san};
+use crate::uci_engine::{plays_variant, EngineSettings, UciEngine};
+use crate::variant::GameVariant;
+
+const MIN_ENGINE_MOVETIME: Duration = Duration::from_millis(10);
 
 #[derive(Debug)]
 pub enum ChannelMsg {
@@ -17,40 +27,104 @@ pub enum ChannelMsg {
     Msg(u32, JsonMsg),
     Disconnect(u32),
     ValueMonitor,
+    // room id, the request being answered and the engine's move
+    EngineMove(u32, u64, Option<Move>),
+}
+
+// the engine's seat in a room, `id` stands in for a websocket id in `boards`
+struct EnginePlayer {
+    id: u32,
+    settings: EngineSettings,
+    // numbers the positions sent, only the answer to the latest one is played
+    request: u64,
+    // the request number, the position to move in and the time to think
+    positions: Sender<(u64, Board, Duration)>,
+}
+
+impl EnginePlayer {
+    // the position the engine is thinking about is no longer on the board
+    fn cancel(&mut self) {
+        self.request += 1;
+    }
+}
+
+// offers waiting for the opponent's answer, by the colour which made them
+#[derive(Default)]
+struct PendingOffers {
+    draw: Option<Color>,
+    takeback: Option<Color>,
 }
 
-pub fn handle_game(receiver: Receiver<ChannelMsg>) {
+pub fn handle_game(receiver: Receiver<ChannelMsg>, sender: Sender<ChannelMsg>) {
     let mut boards: BoardsType = HashMap::new();
     //todo move white_id and black_id out of the "boards" variable, set them on JOIN message
     let mut clients: HashMap<u32, WebSocket<TcpStream>> = HashMap::new();
+    let mut engines: HashMap<u32, EnginePlayer> = HashMap::new();
+    // only timed rooms have a clock
+    let mut clocks: HashMap<u32, Clock> = HashMap::new();
+    let mut offers: HashMap<u32, PendingOffers> = HashMap::new();
+    let mut spectators: SpectatorsType = HashMap::new();
 
     loop {
         log::debug!("Waiting for message...");
-        let msg = receiver.recv().expect("Cannot receive");
+        // wake up when the first running clock runs out, even if nobody sends anything
+        let next_flag = clocks.values().filter_map(|clock| clock.time_to_flag(Instant::now())).min();
+        let msg = match next_flag {
+            None => receiver.recv().expect("Cannot receive"),
+            Some(timeout) => match receiver.recv_timeout(timeout) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => {
+                    check_flags(&mut boards, &mut clocks, &spectators, &clients);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => panic!("Cannot receive"),
+            },
+        };
         log::debug!("Msg received");
         match msg {
             ChannelMsg::NewConnection(websocket_id, websocket) => {
                 let ws_clone = clone_ws(&websocket);
                 clients.insert(websocket_id, websocket);
-                broadcast_rooms_message(&boards, &mut HashMap::from([(websocket_id, ws_clone)]));
+                broadcast_rooms_message(&boards, &spectators, &mut HashMap::from([(websocket_id, ws_clone)]));
                 broadcast_players_online(&mut clients);
             }
 
             ChannelMsg::Msg(websocket_id, decoded) => {
-                let mut websocket = clone_ws(&clients.get(&websocket_id).expect("Cannot find client"));
+                let mut websocket = clone_ws(clients.get(&websocket_id).expect("Cannot find client"));
                 match decoded.msg_type {
                     MsgType::Create => {
+                        if decoded.engine.is_some() && !plays_variant(decoded.variant) {
+                            log::warn!("No engine room for {:?}, the engine does not know its rules", decoded.variant);
+                            send_room_refused(&mut websocket, format!("The computer cannot play {:?}", decoded.variant));
+                            continue;
+                        }
                         let board_id: u32 = random();
-                        let mut new_board = new_board();
+                        let mut new_board = new_game(decoded.variant, decoded.start_position);
                         match decoded.room_name {
-                            None => {}
-                            Some(name) => if name.len() < 100 {
+                            Some(name) if name.len() < 100 => {
                                 new_board.name = name;
                             }
+                            _ => {}
                         };
-                        // let ws = WebSocket::from_raw_socket(stream_clone, Role::Server, Some(websocket.get_config().clone()));
+                        // let ws = WebSocket::from_raw_socket(stream_clone, Role::Server, Some(*websocket.get_config()));
                         let is_white: bool = random();
                         log::debug!("is white {}", is_white);
+                        if let Some(control) = decoded.time_control.and_then(|control| control.checked()) {
+                            clocks.insert(board_id, Clock::new(control));
+                        }
+                        if let Some(settings) = decoded.engine {
+                            // the engine takes the other seat straight away, so the room is full and never listed
+                            let engine = start_engine(board_id, settings, new_board.variant, sender.clone());
+                            let (white, black) = if is_white { (Some(websocket_id), Some(engine.id)) } else { (Some(engine.id), Some(websocket_id)) };
+                            let clock = start_clock(&mut clocks, board_id);
+                            send_new_room(&mut websocket, board_id, is_white);
+                            send_board_update(&mut websocket, &new_board, None, None, None, clock);
+                            engines.insert(board_id, engine);
+                            engine_turn(&mut engines, board_id, &new_board, white, clocks.get(&board_id));
+                            boards.insert(board_id, (new_board, white, black));
+                            log::debug!("Engine room created");
+                            continue;
+                        }
                         let (white, black) = if is_white {
                             (Some(websocket_id), None)
                         } else {
@@ -58,7 +132,7 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                         };
                         boards.insert(board_id, (new_board, white, black));
 
-                        broadcast_rooms_message(&boards, &mut clients);
+                        broadcast_rooms_message(&boards, &spectators, &mut clients);
                         let ws = clients.get_mut(&websocket_id).expect("Cannot find");
                         send_new_room(ws, board_id, is_white);
                         log::debug!("Done");
@@ -73,18 +147,20 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                             Some((b, white_player, black_player)) => {
                                 match (white_player, black_player) {
                                     (None, Some(black)) => {
+                                        let clock = start_clock(&mut clocks, room_id);
                                         send_new_room(&mut websocket, room_id, true);
-                                        send_board_update(&mut websocket, &b, None, None);
+                                        send_board_update(&mut websocket, b, None, None, None, clock);
                                         let ws = clients.get_mut(black).expect("Cannot get");
-                                        send_board_update(ws, &b, None, None);
-                                        (Some(b.clone()), Some(websocket_id), Some(black.clone()))
+                                        send_board_update(ws, b, None, None, None, clock);
+                                        (Some(b.clone()), Some(websocket_id), Some(*black))
                                     }
                                     (Some(white), None) => {
+                                        let clock = start_clock(&mut clocks, room_id);
                                         send_new_room(&mut websocket, room_id, false);
-                                        send_board_update(&mut websocket, &b, None, None);
+                                        send_board_update(&mut websocket, b, None, None, None, clock);
                                         let ws = clients.get_mut(white).expect("Cannot get");
-                                        send_board_update(ws, &b, None, None);
-                                        (Some(b.clone()), Some(white.clone()), Some(websocket_id))
+                                        send_board_update(ws, b, None, None, None, clock);
+                                        (Some(b.clone()), Some(*white), Some(websocket_id))
                                     }
                                     _ => {
                                         log::warn!("Cannot join full room");
@@ -98,21 +174,43 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                             Some(b) => {
                                 boards.remove(&room_id);
                                 boards.insert(room_id, (b, new_white, new_black));
-                                broadcast_rooms_message(&boards, &mut clients);
+                                broadcast_rooms_message(&boards, &spectators, &mut clients);
                                 log::debug!("join done");
                             }
                         }
                     }
                     MsgType::Rematch => {
                         let room_id = decoded.room_id;
+                        // spectators may not take an empty seat
+                        if spectators.get(&room_id).is_some_and(|watching| watching.contains(&websocket_id)) {
+                            continue;
+                        }
                         let (old_board, white, black) = match boards.get(&room_id) {
                             None => {
                                 log::warn!("Cannot find rematch room {}", room_id);
-                                return;
+                                continue;
                             }
                             Some((board, white, black)) => (board, *white, *black)
                         };
 
+                        if engines.contains_key(&room_id) {
+                            // the engine always accepts, colours are swapped as in any rematch
+                            if old_board.game_over && (white == Some(websocket_id) || black == Some(websocket_id)) {
+                                let new_board = new_game(old_board.variant, None);
+                                let (white, black) = (black, white);
+                                let clock = start_clock(&mut clocks, room_id);
+                                offers.remove(&room_id);
+                                send_new_room(&mut websocket, room_id, white == Some(websocket_id));
+                                send_board_update(&mut websocket, &new_board, None, None, None, clock);
+                                engine_turn(&mut engines, room_id, &new_board, white, clocks.get(&room_id));
+                                for mut socket in room_sockets(&clients, [None, None], spectators.get(&room_id)) {
+                                    send_game_so_far(&mut socket, room_id, &new_board, clock);
+                                }
+                                boards.insert(room_id, (new_board, white, black));
+                            }
+                            continue;
+                        }
+
                         if old_board.game_over {
                             match (white, black) {
                                 (Some(white), Some(black)) => {
@@ -135,21 +233,31 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                                     }
                                 }
                                 (Some(white), None) if white != websocket_id => {
-                                    let new_board = new_board();
+                                    let new_board = new_game(old_board.variant, None);
+                                    let clock = start_clock(&mut clocks, room_id);
+                                    offers.remove(&room_id);
                                     let white_socket = clients.get_mut(&white).expect("Cannot find");
                                     send_new_room(white_socket, room_id, true);
-                                    send_board_update(white_socket, &new_board, None, None);
+                                    send_board_update(white_socket, &new_board, None, None, None, clock);
                                     send_new_room(&mut websocket, room_id, false);
-                                    send_board_update(&mut websocket, &new_board, None, None);
+                                    send_board_update(&mut websocket, &new_board, None, None, None, clock);
+                                    for mut socket in room_sockets(&clients, [None, None], spectators.get(&room_id)) {
+                                        send_game_so_far(&mut socket, room_id, &new_board, clock);
+                                    }
                                     boards.insert(room_id, (new_board, Some(white), Some(websocket_id)));
                                 }
                                 (None, Some(black)) if black != websocket_id => {
-                                    let new_board = new_board();
+                                    let new_board = new_game(old_board.variant, None);
+                                    let clock = start_clock(&mut clocks, room_id);
+                                    offers.remove(&room_id);
                                     let black_socket = clients.get_mut(&black).expect("Cannot find");
                                     send_new_room(black_socket, room_id, false);
-                                    send_board_update(black_socket, &new_board, None, None);
+                                    send_board_update(black_socket, &new_board, None, None, None, clock);
                                     send_new_room(&mut websocket, room_id, true);
-                                    send_board_update(&mut websocket, &new_board, None, None);
+                                    send_board_update(&mut websocket, &new_board, None, None, None, clock);
+                                    for mut socket in room_sockets(&clients, [None, None], spectators.get(&room_id)) {
+                                        send_game_so_far(&mut socket, room_id, &new_board, clock);
+                                    }
                                     boards.insert(room_id, (new_board, Some(websocket_id), Some(black)));
                                 }
                                 _ => {
@@ -161,63 +269,166 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                     MsgType::Move => {
                         // todo get room id from memory, not from the message
                         let room_id = decoded.room_id;
-                        let (move_from, move_to) = decoded.make_move.expect("Move must be provided");
-                        let is_legal_move = match boards.get(&room_id) {
+                        let Some((move_from, move_to)) = decoded.make_move else {
+                            log::warn!("Move message without a move from {}", websocket_id);
+                            continue;
+                        };
+                        let promotion = decoded.promotion.unwrap_or(Queen);
+                        // a move sent after the flag fell comes too late
+                        check_flags(&mut boards, &mut clocks, &spectators, &clients);
+                        let legal_move = match boards.get(&room_id) {
                             Some((board, Some(white), Some(black))) => {
                                 let player_color = match websocket_id {
                                     x if x == *white => White,
                                     x if x == *black => Black,
                                     x => {
-                                        log::warn!("Unexpected websocket id: {}", {x});
-                                        White
+                                        log::warn!("Move from {} who does not play in room {}", x, room_id);
+                                        continue;
                                     }
                                 };
-                                board.color_to_play() == player_color && allowed_moves(board, move_from.0, move_from.1, player_color).contains(&move_to)
-                                // allowed_moves(board, move_from.0, move_from.1, player_color).contains(&move_to)
+                                // a promotion must name the piece the client picked, other moves ignore it
+                                allowed_moves(board, move_from, player_color).into_iter()
+                                    .filter(|_| board.color_to_play() == player_color && !board.game_over)
+                                    .find(|m| m.to == move_to && m.promotion().is_none_or(|kind| kind == promotion))
                             }
-                            _ => false
+                            _ => None
                         };
 
-                        if is_legal_move {
+                        if let Some(chess_move) = legal_move {
                             let (board, white, black) = boards.get_mut(&room_id).expect("Board must be provided");
-                            board.make_move(move_from, move_to);
-                            let king_pos = board.king_positions[&board.color_to_play()];
-                            let in_check = all_potential_attacks(board)[&board.color_to_play().opposite()].contains(&king_pos).then_some(king_pos);
-
-                            let client_white = clients.get(&white.expect("Must be provided")).expect("Must be provided");
-                            let client_black = clients.get(&black.expect("Must be provided")).expect("Must be provided");
-                            send_board_update(&mut clone_ws(client_white), board, Some((move_from, move_to)), in_check);
-                            send_board_update(&mut clone_ws(client_black), board, Some((move_from, move_to)), in_check);
-
-                            match game_result(board) {
-                                GameStatus::InProgress => {
-                                    board.game_over = false;
-                                }
-                                GameStatus::Win(c) => {
-                                    send_game_over(&mut clone_ws(client_white), Some(c));
-                                    send_game_over(&mut clone_ws(client_black), Some(c));
+                            let sockets = room_sockets(&clients, [*white, *black], spectators.get(&room_id));
+                            play_move(room_id, board, chess_move, sockets, clocks.get_mut(&room_id));
+                            offers.remove(&room_id);
+                            engine_turn(&mut engines, room_id, board, *white, clocks.get(&room_id));
+                        }
+                        log::debug!("Move done");
+                    }
+                    MsgType::ClaimDraw => {
+                        let room_id = decoded.room_id;
+                        if let Some((board, Some(white), Some(black))) = boards.get_mut(&room_id) {
+                            let is_player = websocket_id == *white || websocket_id == *black;
+                            match claimable_draw(board) {
+                                Some(reason) if is_player && !board.game_over => {
+                                    log::info!("Room {}: draw claimed, {:?}", room_id, reason);
+                                    for mut socket in room_sockets(&clients, [Some(*white), Some(*black)], spectators.get(&room_id)) {
+                                        send_game_over(&mut socket, GameStatus::Draw(reason));
+                                    }
                                     board.game_over = true;
+                                    stop_clock(&mut clocks, room_id);
                                 }
-                                GameStatus::Draw => {
-                                    send_game_over(&mut clone_ws(client_white), None);
-                                    send_game_over(&mut clone_ws(client_black), None);
-                                    board.game_over = true;
+                                _ => log::debug!("Draw cannot be claimed in room {}", room_id),
+                            }
+                        }
+                    }
+                    MsgType::Resign | MsgType::OfferDraw | MsgType::AcceptDraw | MsgType::DeclineDraw
+                    | MsgType::RequestTakeback | MsgType::AcceptTakeback | MsgType::DeclineTakeback => {
+                        let room_id = decoded.room_id;
+                        let Some((board, Some(white), Some(black))) = boards.get_mut(&room_id) else {
+                            continue;
+                        };
+                        let (white, black) = (*white, *black);
+                        let color = match websocket_id {
+                            x if x == white => White,
+                            x if x == black => Black,
+                            x => {
+                                log::warn!("{:?} from {} who does not play in room {}", decoded.msg_type, x, room_id);
+                                continue;
+                            }
+                        };
+                        if board.game_over {
+                            continue;
+                        }
+                        // the engine turns down draws and grants every takeback
+                        let engine = engines.get_mut(&room_id);
+                        let pending = offers.entry(room_id).or_default();
+                        let opponent = color.opposite();
+                        let sockets: Vec<(Color, WebSocket<TcpStream>)> = [(White, white), (Black, black)].into_iter()
+                            .filter_map(|(seat, id)| clients.get(&id).map(|client| (seat, clone_ws(client))))
+                            .collect();
+                        // results and takebacks go to the spectators as well, offers only to the players
+                        let audience = room_sockets(&clients, [Some(white), Some(black)], spectators.get(&room_id));
+                        match decoded.msg_type {
+                            MsgType::Resign => {
+                                let status = GameStatus::Win(opponent, WinReason::Resignation);
+                                log::info!("Room {}: {:?} resigned", room_id, color);
+                                for mut socket in audience {
+                                    send_game_over(&mut socket, status);
+                                }
+                                board.game_over = true;
+                                stop_clock(&mut clocks, room_id);
+                            }
+                            MsgType::OfferDraw if engine.is_some() => send_offer_declined(&mut websocket, true),
+                            MsgType::OfferDraw if pending.draw.is_none() => {
+                                pending.draw = Some(color);
+                                for (seat, mut socket) in sockets {
+                                    send_draw_offer(&mut socket, seat == color);
+                                }
+                            }
+                            MsgType::AcceptDraw if pending.draw == Some(opponent) => {
+                                log::info!("Room {}: draw agreed", room_id);
+                                for mut socket in audience {
+                                    send_game_over(&mut socket, GameStatus::Draw(DrawReason::Agreement));
+                                }
+                                board.game_over = true;
+                                stop_clock(&mut clocks, room_id);
+                            }
+                            MsgType::DeclineDraw if pending.draw == Some(opponent) => {
+                                pending.draw = None;
+                                for (_, mut socket) in sockets {
+                                    send_offer_declined(&mut socket, true);
+                                }
+                            }
+                            MsgType::RequestTakeback if takeback_plies(board, color).is_some() && pending.takeback.is_none() => {
+                                match engine {
+                                    Some(engine) => {
+                                        engine.cancel();
+                                        take_back(room_id, board, color, audience, clocks.get_mut(&room_id));
+                                        // an offer made before the takeback no longer stands
+                                        *pending = PendingOffers::default();
+                                    }
+                                    None => {
+                                        pending.takeback = Some(color);
+                                        for (seat, mut socket) in sockets {
+                                            send_takeback_request(&mut socket, seat == color);
+                                        }
+                                    }
+                                }
+                            }
+                            MsgType::AcceptTakeback if pending.takeback == Some(opponent) => {
+                                take_back(room_id, board, opponent, audience, clocks.get_mut(&room_id));
+                                *pending = PendingOffers::default();
+                            }
+                            MsgType::DeclineTakeback if pending.takeback == Some(opponent) => {
+                                pending.takeback = None;
+                                for (_, mut socket) in sockets {
+                                    send_offer_declined(&mut socket, false);
                                 }
-                            };
+                            }
+                            msg_type => log::debug!("Room {}: {:?} from {:?} does not apply", room_id, msg_type, color),
+                        }
+                    }
+                    MsgType::Spectate => {
+                        let room_id = decoded.room_id;
+                        match boards.get(&room_id) {
+                            Some((board, Some(white), Some(black))) if websocket_id != *white && websocket_id != *black => {
+                                log::info!("Room {}: {} is watching", room_id, websocket_id);
+                                let clock = clocks.get(&room_id).map(|clock| clock.state(Instant::now()));
+                                send_game_so_far(&mut websocket, room_id, board, clock);
+                                spectators.entry(room_id).or_default().insert(websocket_id);
+                                broadcast_rooms_message(&boards, &spectators, &mut clients);
+                            }
+                            _ => log::warn!("Cannot spectate room {}", room_id),
                         }
-                        log::debug!("Move done");
                     }
                     MsgType::Possible => {
                         match decoded.possible_moves {
                             None => {}
-                            Some((row, col)) => {
-                                match boards.get(&decoded.room_id) {
-                                    Some((board, Some(white_id), Some(black_id))) => {
-                                        let my_color = get_player_color(websocket_id, *white_id, *black_id);
-                                        let moves = allowed_moves(&board, row, col, my_color);
+                            Some(square) => {
+                                if let Some((board, Some(white_id), Some(black_id))) = boards.get(&decoded.room_id) {
+                                    if let Some(my_color) = get_player_color(websocket_id, *white_id, *black_id) {
+                                        let moves = allowed_moves(board, square, my_color).iter().map(|m| m.to).collect();
                                         send_possible_moves(clients.get_mut(&websocket_id).expect("Must be provided"), moves);
                                     }
-                                    _ => {}
                                 }
                             }
                         };
@@ -234,38 +445,57 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                 // todo this way a consistency would be persevered, only client initiate websocket disconnect
                 log::debug!("Removing client {}", client_id);
                 clients.remove(&client_id);
+                let mut stopped_watching = false;
+                for watching in spectators.values_mut() {
+                    stopped_watching |= watching.remove(&client_id);
+                }
                 // todo store board_id in clients instead of searching it
                 // todo disconnect both websockets, notify players about game disconnect and game result
 
                 // if white_id.is_some() && white_id.unwrap() == client_id || black_id.is_some() && black_id.unwrap() == client_id { Some(board_id) } else { None })
-                let id_socket: Vec<(u32, Option<u32>)> = boards.iter()
+                let id_socket: Vec<(u32, Option<u32>, Color)> = boards.iter()
                     .filter_map(|(&board_id, (_b, white_id, black_id))| {
                         if white_id.is_some_and(|x| x == client_id) {
-                            Some((board_id, *black_id))
+                            Some((board_id, *black_id, Black))
                         } else if black_id.is_some_and(|x| x == client_id) {
-                            Some((board_id, *white_id))
+                            Some((board_id, *white_id, White))
                         } else {
                             None
                         }
                     })
                     .collect();
 
-                let notify_rooms = id_socket.iter().any(|(_, client)| client.is_none());
+                // in-progress games are listed too, so losing any room changes the list
+                let notify_rooms = !id_socket.is_empty() || stopped_watching;
 
-                for (board_id, potential_opponent) in id_socket {
+                for (board_id, potential_opponent, opponent_color) in id_socket {
                     log::debug!("Removing board {}", board_id);
-                    boards.remove(&board_id);
+                    // dropping the engine's channel ends its thread and process
+                    engines.remove(&board_id);
+                    clocks.remove(&board_id);
+                    offers.remove(&board_id);
+                    let abandoned = boards.remove(&board_id).is_some_and(|(board, _, _)| !board.game_over);
+                    for mut socket in room_sockets(&clients, [None, None], spectators.remove(&board_id).as_ref()) {
+                        if abandoned {
+                            send_game_over(&mut socket, GameStatus::Win(opponent_color, WinReason::Abandonment));
+                        }
+                        send_opponent_disconnect(&mut socket);
+                    }
                     match potential_opponent.and_then(|x| clients.remove(&x)) {
                         None => {}
                         Some(mut socket) => {
                             log::debug!("Disconnection notifying");
+                            // leaving a game which is still going on loses it
+                            if abandoned {
+                                send_game_over(&mut socket, GameStatus::Win(opponent_color, WinReason::Abandonment));
+                            }
                             send_opponent_disconnect(&mut socket);
                         }
                     };
                 }
 
                 if notify_rooms {
-                    broadcast_rooms_message(&boards, &mut clients);
+                    broadcast_rooms_message(&boards, &spectators, &mut clients);
                 }
 
                 broadcast_players_online(&mut clients);
@@ -275,28 +505,246 @@ pub fn handle_game(receiver: Receiver<ChannelMsg>) {
                 log::info!("Clients: {}", clients.len());
                 log::info!("{:?}", clients.keys());
                 log::info!("Boards: {}", boards.len());
+                log::info!("Engines: {}", engines.len());
+                log::info!("Spectators: {}", spectators.values().map(|watching| watching.len()).sum::<usize>());
                 for (board_id, (_b, white, black)) in &boards {
                     log::info!("({} - ({:?}, {:?}))", board_id, white, black);
                 }
             }
+
+            ChannelMsg::EngineMove(room_id, request, engine_move) => {
+                check_flags(&mut boards, &mut clocks, &spectators, &clients);
+                // the room may have been closed, the game finished or a move taken back while the engine was thinking
+                let Some((board, white, black)) = boards.get_mut(&room_id) else {
+                    continue;
+                };
+                if board.game_over || engines.get(&room_id).is_none_or(|engine| engine.request != request) {
+                    log::debug!("Dropping stale engine move in room {}", room_id);
+                    continue;
+                }
+                // castling may come in either notation, so moves are compared by where the king ends up
+                let color = board.color_to_play();
+                let legal_move = engine_move.and_then(|engine_move| allowed_moves(board, engine_move.from, color).into_iter()
+                    .find(|m| m.destination() == engine_move.destination() && m.promotion() == engine_move.promotion()));
+                match legal_move {
+                    Some(chess_move) => {
+                        let sockets = room_sockets(&clients, [*white, *black], spectators.get(&room_id));
+                        play_move(room_id, board, chess_move, sockets, clocks.get_mut(&room_id));
+                        offers.remove(&room_id);
+                    }
+                    None => {
+                        // an engine which crashed or sent nonsense loses like a player who left
+                        log::error!("Room {}: no legal move from the engine, got {:?}", room_id, engine_move);
+                        let status = GameStatus::Win(color.opposite(), WinReason::Abandonment);
+                        for mut socket in room_sockets(&clients, [*white, *black], spectators.get(&room_id)) {
+                            send_game_over(&mut socket, status);
+                        }
+                        board.game_over = true;
+                        stop_clock(&mut clocks, room_id);
+                    }
+                }
+            }
         }
     }
 }
 
-fn get_player_color(websocket_id: u32, white_id: u32, black_id: u32) -> Color {
+// `None` for spectators
+fn get_player_color(websocket_id: u32, white_id: u32, black_id: u32) -> Option<Color> {
     if websocket_id == white_id {
-        White
+        Some(White)
     }
     else if websocket_id == black_id {
-        Black
+        Some(Black)
     }
     else {
-        log::warn!("Cannot find player color, websocket_id: {}", websocket_id);
-        White
+        log::debug!("Cannot find player color, websocket_id: {}", websocket_id);
+        None
     }
 }
 
 fn clone_ws(websocket: &WebSocket<TcpStream>) -> WebSocket<TcpStream> {
     let tcp_stream: TcpStream = websocket.get_ref().try_clone().unwrap();
-    WebSocket::from_raw_socket(tcp_stream, Role::Server, Some(websocket.get_config().clone()))
+    WebSocket::from_raw_socket(tcp_stream, Role::Server, Some(*websocket.get_config()))
+}
+
+// a Chess960 room starts from the requested position, or a random one if none (or an invalid one) was asked for
+fn new_game(variant: GameVariant, start_position: Option<usize>) -> Board {
+    match start_position.and_then(new_chess960_board) {
+        Some(board) if variant == GameVariant::Chess960 => board,
+        _ => variant.rules().start_position(),
+    }
+}
+
+// plays a move already checked to be legal and tells everyone in `sockets`
+fn play_move(room_id: u32, board: &mut Board, chess_move: Move, sockets: Vec<WebSocket<TcpStream>>, mut clock: Option<&mut Clock>) {
+    let san = to_san(board, &chess_move);
+    log::info!("Room {}: {}", room_id, san);
+    board.make_move(chess_move);
+    let in_check = king_in_check(board);
+
+    let now = Instant::now();
+    let result = board.variant.rules().game_result(board);
+    if let Some(clock) = clock.as_mut() {
+        if result == GameStatus::InProgress { clock.press(now) } else { clock.stop(now) }
+    }
+    let clock_state = clock.map(|clock| clock.state(now));
+
+    for socket in &sockets {
+        send_board_update(&mut clone_ws(socket), board, Some((chess_move.from, chess_move.to)), Some(san.clone()), in_check, clock_state);
+    }
+
+    match result {
+        GameStatus::InProgress => {
+            board.game_over = false;
+        }
+        status => {
+            log::info!("Room {}: game over, {:?}", room_id, status);
+            for socket in &sockets {
+                send_game_over(&mut clone_ws(socket), status);
+            }
+            board.game_over = true;
+        }
+    };
+}
+
+// both players and everyone watching, engines have no socket and are skipped
+fn room_sockets(clients: &ClientsType, players: [Option<u32>; 2], watching: Option<&HashSet<u32>>) -> Vec<WebSocket<TcpStream>> {
+    players.iter().flatten().chain(watching.into_iter().flatten())
+        .filter_map(|id| clients.get(id))
+        .map(clone_ws)
+        .collect()
+}
+
+// replays the game from its first position so a spectator gets the move list, the clocks come with the last board
+fn send_game_so_far(socket: &mut WebSocket<TcpStream>, room_id: u32, board: &Board, clock: Option<ClockState>) {
+    send_spectating(socket, room_id, board.name.clone());
+    let mut replay = board.clone();
+    while replay.unmake_move().is_some() {}
+    let last = board.move_history.len();
+    send_board_update(socket, &replay, None, None, None, clock.filter(|_| last == 0));
+    for (i, &m) in board.move_history.iter().enumerate() {
+        let san = to_san(&replay, &m);
+        replay.make_move(m);
+        send_board_update(socket, &replay, Some((m.from, m.to)), Some(san), king_in_check(&replay), clock.filter(|_| i + 1 == last));
+    }
+}
+
+// the square of the side to move's king when it is in check
+fn king_in_check(board: &Board) -> Option<Square> {
+    let color = board.color_to_play();
+    Position::from_board(board, color).in_check(color).then(|| board.king_positions[&color])
+}
+
+// how many plies undo `color`'s last move, together with the opponent's reply if there was one
+fn takeback_plies(board: &Board, color: Color) -> Option<usize> {
+    let plies = if board.color_to_play() == color { 2 } else { 1 };
+    (board.move_history.len() >= plies).then_some(plies)
+}
+
+// undoes `color`'s last move, the clock goes on running for whoever is to move now
+fn take_back(room_id: u32, board: &mut Board, color: Color, sockets: Vec<WebSocket<TcpStream>>, clock: Option<&mut Clock>) {
+    let Some(plies) = takeback_plies(board, color) else {
+        return;
+    };
+    for _ in 0..plies {
+        board.unmake_move();
+    }
+    log::info!("Room {}: {} plies taken back for {:?}", room_id, plies, color);
+    let now = Instant::now();
+    let clock_state = clock.map(|clock| {
+        clock.start(board.color_to_play(), now);
+        clock.state(now)
+    });
+    let in_check = king_in_check(board);
+    for mut socket in sockets {
+        send_takeback(&mut socket, plies, in_check, clock_state);
+    }
+}
+
+// asks the room's engine, if it has one, for a move when it is the engine's turn,
+// on a clock it thinks for at most a twentieth of its remaining time
+fn engine_turn(engines: &mut HashMap<u32, EnginePlayer>, room_id: u32, board: &Board, white: Option<u32>, clock: Option<&Clock>) {
+    let Some(engine) = engines.get_mut(&room_id) else {
+        return;
+    };
+    let engine_color = if white == Some(engine.id) { White } else { Black };
+    if board.game_over || board.color_to_play() != engine_color {
+        return;
+    }
+    // with almost nothing left on the clock the engine still gets a moment, rather than `go movetime 0`
+    let movetime = clock.map_or(engine.settings.movetime(), |clock| {
+        engine.settings.movetime().min(clock.remaining(engine_color, Instant::now()) / 20).max(MIN_ENGINE_MOVETIME)
+    });
+    engine.request += 1;
+    if engine.positions.send((engine.request, board.clone(), movetime)).is_err() {
+        log::error!("Room {}: engine thread is gone", room_id);
+    }
+}
+
+// (re)starts a room's clock from the full time with white to move, `None` for untimed rooms
+fn start_clock(clocks: &mut HashMap<u32, Clock>, room_id: u32) -> Option<ClockState> {
+    let clock = clocks.get_mut(&room_id)?;
+    let now = Instant::now();
+    *clock = Clock::new(clock.control);
+    clock.start(White, now);
+    Some(clock.state(now))
+}
+
+fn stop_clock(clocks: &mut HashMap<u32, Clock>, room_id: u32) {
+    if let Some(clock) = clocks.get_mut(&room_id) {
+        clock.stop(Instant::now());
+    }
+}
+
+// ends the games whose side to move has run out of time, it is a draw when the opponent has nothing left to mate with
+fn check_flags(boards: &mut BoardsType, clocks: &mut HashMap<u32, Clock>, spectators: &SpectatorsType, clients: &ClientsType) {
+    let now = Instant::now();
+    for (room_id, clock) in clocks.iter_mut() {
+        let Some(color) = clock.flagged(now) else {
+            continue;
+        };
+        clock.stop(now);
+        let Some((board, white, black)) = boards.get_mut(room_id).filter(|(board, _, _)| !board.game_over) else {
+            continue;
+        };
+        let status = if has_mating_material(board, color.opposite()) {
+            GameStatus::Win(color.opposite(), WinReason::Timeout)
+        } else {
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        };
+        log::info!("Room {}: {:?} ran out of time, {:?}", room_id, color, status);
+        for mut socket in room_sockets(clients, [*white, *black], spectators.get(room_id)) {
+            send_game_over(&mut socket, status);
+        }
+        board.game_over = true;
+    }
+}
+
+// the engine thinks on its own thread so other rooms are not held up, it quits once its room is removed
+fn start_engine(room_id: u32, settings: EngineSettings, variant: GameVariant, events: Sender<ChannelMsg>) -> EnginePlayer {
+    let (positions, requests) = channel::<(u64, Board, Duration)>();
+    spawn(move || {
+        let path = engine_path();
+        let mut engine = UciEngine::start(&path, settings.skill(), variant == GameVariant::Chess960)
+            .map_err(|e| log::error!("Cannot start engine {:?}: {}", path, e))
+            .ok();
+        for (request, board, movetime) in requests {
+            let best_move = engine.as_mut().and_then(|engine| engine.best_move(&board, movetime)
+                .map_err(|e| log::error!("Room {}: engine error: {}", room_id, e))
+                .ok()
+                .flatten());
+            if events.send(ChannelMsg::EngineMove(room_id, request, best_move)).is_err() {
+                break;
+            }
+        }
+    });
+    EnginePlayer { id: random(), settings, request: 0, positions }
+}
+
+// UCI_ENGINE_PATH if set, otherwise the `uci` binary built next to the server
+fn engine_path() -> PathBuf {
+    match env::var_os("UCI_ENGINE_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => env::current_exe().expect("Cannot find the server executable").with_file_name(format!("uci{}", env::consts::EXE_SUFFIX)),
+    }
 }
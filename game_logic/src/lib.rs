@@ -1,3 +1,12 @@
 pub mod communication_protocol;
 pub mod board;
 pub mod moves;
+pub mod bitboard;
+pub mod pgn;
+pub mod perft;
+pub mod zobrist;
+pub mod variant;
+pub mod eval;
+pub mod search;
+pub mod uci_engine;
+pub mod clock;
@@ -1,29 +1,29 @@
 use std::collections::{HashMap, HashSet};
-use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::{current, sleep, spawn};
 use std::time::{Duration, Instant};
 
 use rand::random;
-use tungstenite::{accept, HandshakeError, Message, ServerHandshake, WebSocket};
-use tungstenite::handshake::server::NoCallback;
+use tungstenite::{accept, Message, WebSocket};
 use tungstenite::protocol::Role;
 
-use crate::board::{Board, Color, new_board, to_string};
+use crate::board::{Board, Color, GameStatus, Square, to_string};
 use crate::board::Color::{Black, White};
-use crate::communication_protocol::{JsonMsg, JsonMsgServer, MsgType, MsgTypeServer, ServerMsg};
-use crate::communication_protocol::MsgType::Rematch;
+use crate::clock::ClockState;
+use crate::communication_protocol::{JsonMsg, JsonMsgServer, MsgTypeServer, ServerMsg};
 use crate::game_server::ChannelMsg;
 
-mod moves;
-mod board;
-mod communication_protocol;
+use chess_logic_lib::{bitboard, board, clock, communication_protocol, moves, uci_engine, variant};
+
 mod game_server;
 
 type BoardsType = HashMap<u32, (Board, Option<u32>, Option<u32>)>;
 type ClientsType = HashMap<u32, WebSocket<TcpStream>>;
+// client ids watching each room
+type SpectatorsType = HashMap<u32, HashSet<u32>>;
 
+#[allow(dead_code)]
 fn draw_board(board: &Board) {
     let s = to_string(board);
     for c in s.chars() {
@@ -50,15 +50,19 @@ fn try_send(ws: &mut WebSocket<TcpStream>, msg: String) {
 }
 
 // todo broadcasting to everyone may take a lot of time, better to create a separate thread for sending in a loop cases
-fn broadcast_rooms_message(boards: &BoardsType, clients: &mut ClientsType) {
+fn broadcast_rooms_message(boards: &BoardsType, spectators: &SpectatorsType, clients: &mut ClientsType) {
     let i = Instant::now();
     log::debug!("Sending rooms to {} clients", clients.len());
     let room_names: Vec<(u32, String)> = boards.iter()
-        .filter(|(room_id, (b, white, black))| (white.is_some() ^ black.is_some()) && !b.game_over)
+        .filter(|(_room_id, (b, white, black))| (white.is_some() ^ black.is_some()) && !b.game_over)
         .map(|(&room_id, (b, _, _))| (room_id, b.name.clone()))
         .collect();
+    let games: Vec<(u32, String, usize)> = boards.iter()
+        .filter(|(_room_id, (b, white, black))| white.is_some() && black.is_some() && !b.game_over)
+        .map(|(&room_id, (b, _, _))| (room_id, b.name.clone(), spectators.get(&room_id).map_or(0, |watching| watching.len())))
+        .collect();
     // let server_msg = JsonMsgServer { msg_type: MsgTypeServer::Rooms, board: None, rooms: rooms_id, room_id: None, color: None, possible_moves: HashSet::new() };
-    let server_msg = ServerMsg::Rooms {room_names};
+    let server_msg = ServerMsg::Rooms {room_names, games};
     let msg = serde_json::to_string(&server_msg).expect("Cannot serialize");
 
     for ws in clients.values_mut() {
@@ -79,33 +83,37 @@ fn broadcast_players_online (clients: &mut ClientsType) {
 }
 
 fn send_new_room(socket: &mut WebSocket<TcpStream>, room_id: u32, is_white: bool) {
-    let msg = JsonMsgServer { msg_type: MsgTypeServer::NewRoom, board: None, room_id: Some(room_id), color: Some(if is_white { White } else { Black }), possible_moves: HashSet::new() };
+    let msg = JsonMsgServer { msg_type: MsgTypeServer::NewRoom, board: None, room_id: Some(room_id), color: Some(if is_white { White } else { Black }), possible_moves: HashSet::new(), result: None };
     let msg = serde_json::to_string(&msg).expect("Cannot serialize");
     try_send(socket, msg);
 }
 
-fn send_board_update(socket: &mut WebSocket<TcpStream>, board: &Board, last_move: Option<((usize, usize), (usize, usize))>, in_check: Option<(usize, usize)>) {
+fn send_board_update(socket: &mut WebSocket<TcpStream>, board: &Board, last_move: Option<(Square, Square)>, last_move_san: Option<String>, in_check: Option<Square>, clock: Option<ClockState>) {
     let current_board = to_string(board);
-    let msg = ServerMsg::Board {current_board, last_move, in_check};
+    let msg = ServerMsg::Board {current_board, last_move, last_move_san, in_check, clock};
     let msg = serde_json::to_string(&msg).expect("Cannot serialize");
     try_send(socket, msg);
 }
 
-fn send_possible_moves(socket: &mut WebSocket<TcpStream>, moves: HashSet<(usize, usize)>) {
-    let msg = JsonMsgServer { msg_type: MsgTypeServer::Possible, board: None, room_id: None, color: None, possible_moves: moves };
+fn send_possible_moves(socket: &mut WebSocket<TcpStream>, moves: HashSet<Square>) {
+    let msg = JsonMsgServer { msg_type: MsgTypeServer::Possible, board: None, room_id: None, color: None, possible_moves: moves, result: None };
     let msg = serde_json::to_string(&msg).expect("Cannot serialize");
     try_send(socket, msg);
 }
 
-fn send_game_over(socket: &mut WebSocket<TcpStream>, winner: Option<Color>) {
-    let result = match winner {
-        None => MsgTypeServer::GameResultDraw,
-        Some(color) => match color {
-            Color::White => MsgTypeServer::GameResultWhiteWon,
-            Color::Black => MsgTypeServer::GameResultBlackWon,
-        }
+fn send_game_over(socket: &mut WebSocket<TcpStream>, status: GameStatus) {
+    let msg_type = match status {
+        GameStatus::Win(Color::White, _) => MsgTypeServer::GameResultWhiteWon,
+        GameStatus::Win(Color::Black, _) => MsgTypeServer::GameResultBlackWon,
+        _ => MsgTypeServer::GameResultDraw,
     };
-    let msg = JsonMsgServer { msg_type: result, board: None, room_id: None, color: None, possible_moves: HashSet::new() };
+    let msg = JsonMsgServer { msg_type, board: None, room_id: None, color: None, possible_moves: HashSet::new(), result: Some(status) };
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
+fn send_spectating(socket: &mut WebSocket<TcpStream>, room_id: u32, name: String) {
+    let msg = ServerMsg::Spectating {room_id, name};
     let msg = serde_json::to_string(&msg).expect("Cannot serialize");
     try_send(socket, msg);
 }
@@ -122,6 +130,37 @@ fn send_opponent_disconnect(socket: &mut WebSocket<TcpStream>) {
     try_send(socket, msg);
 }
 
+fn send_draw_offer(socket: &mut WebSocket<TcpStream>, my_offer: bool) {
+    let msg = ServerMsg::DrawOffer {my_offer};
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
+fn send_takeback_request(socket: &mut WebSocket<TcpStream>, my_request: bool) {
+    let msg = ServerMsg::TakebackRequest {my_request};
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
+// tells a player that a draw offer (or, with `draw` false, a takeback request) was turned down
+fn send_offer_declined(socket: &mut WebSocket<TcpStream>, draw: bool) {
+    let msg = if draw { ServerMsg::DrawDeclined } else { ServerMsg::TakebackDeclined };
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
+fn send_takeback(socket: &mut WebSocket<TcpStream>, plies: usize, in_check: Option<Square>, clock: Option<ClockState>) {
+    let msg = ServerMsg::Takeback {plies, in_check, clock};
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
+fn send_room_refused(socket: &mut WebSocket<TcpStream>, reason: String) {
+    let msg = ServerMsg::RoomRefused {reason};
+    let msg = serde_json::to_string(&msg).expect("Cannot serialize");
+    try_send(socket, msg);
+}
+
 fn main() {
     let logger_env = env_logger::Env::default().filter_or("LOG_LEVEL", "DEBUG");
     env_logger::Builder::from_env(logger_env).format_timestamp_millis().init();
@@ -130,7 +169,8 @@ fn main() {
 
     let monitor_sender = sender_origin.clone();
     spawn(|| thread_game_monitor(monitor_sender));
-    spawn(|| game_server::handle_game(receiver));
+    let engine_sender = sender_origin.clone();
+    spawn(|| game_server::handle_game(receiver, engine_sender));
 
     // let server = TcpListener::bind("127.0.0.1:9977").expect("Cannot create server");
     let server = TcpListener::bind("0.0.0.0:9977").expect("Cannot create server");
@@ -145,7 +185,7 @@ fn main() {
                 continue
             }
         };
-        let ws_clone = WebSocket::from_raw_socket(tcp_stream_clone, Role::Server, Some(websocket.get_config().clone()));
+        let ws_clone = WebSocket::from_raw_socket(tcp_stream_clone, Role::Server, Some(*websocket.get_config()));
         let client_id: u32 = random();
 
         log::debug!("New connection");
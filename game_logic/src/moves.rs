@@ -1,316 +1,371 @@
 use std::collections::{HashMap, HashSet};
-use crate::board::{Board, Color, HEIGHT, PieceType, WIDTH, Piece, GameStatus};
+use std::fmt;
+use crate::board::{Board, ChessError, Color, DrawReason, HEIGHT, Move, MoveKind, Piece, PieceType, Square, WIDTH, GameStatus, WinReason};
 use crate::board::Color::{Black, White};
+use crate::bitboard::{square_coordinates, square_index, squares_of, Bitboard, Position};
 
-const START_RANK_WHITE: usize = 1;
-const START_RANK_BLACK: usize = 6;
+// both rules count plies since the last capture or pawn move
+pub const FIFTY_MOVE_RULE_PLIES: u32 = 100;
+pub const SEVENTY_FIVE_MOVE_RULE_PLIES: u32 = 150;
 
-fn move_straight(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
-    let current_color = board.squares[row][col].expect("Only occupied squares expected").color;
-    let north = move_by_vector(board, &[1, 0], row, col, &current_color);
-    let south = move_by_vector(board, &[-1, 0], row, col, &current_color);
-    let east = move_by_vector(board, &[0, 1], row, col, &current_color);
-    let west = move_by_vector(board, &[0, -1], row, col, &current_color);
-    [north, south, east, west].concat()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    InvalidMove(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
 }
 
-fn move_diagonally(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
-    let current_color = board.squares[row][col].expect("Only occupied squares expected").color;
-    let north_east = move_by_vector(board, &[1, 1], row, col, &current_color);
-    let south_east = move_by_vector(board, &[-1, 1], row, col, &current_color);
-    let south_west = move_by_vector(board, &[-1, -1], row, col, &current_color);
-    let north_west = move_by_vector(board, &[1, -1], row, col, &current_color);
-    [north_east, south_east, south_west, north_west].concat()
-}
-
-fn move_by_vector(board: &Board, vec: &[i8; 2], row: usize, col: usize, current_color: &Color) -> Vec<(usize, usize)> {
-    let i_row = row as i8;
-    let i_col = col as i8;
-    let mut result: Vec<(usize, usize)> = Vec::new();
-    let mut n_row = i_row + vec[0];
-    let mut n_col = i_col + vec[1];
-    while n_row >= 0 && n_row < (HEIGHT as i8) && n_col >= 0 && n_col < (WIDTH as i8) {
-        let new_row = n_row as usize;
-        let new_col = n_col as usize;
-        match board.squares[new_row][new_col] {
-            None => result.push((new_row, new_col)),
-            Some(_) => {
-                result.push((new_row, new_col));
-                break
-            }
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::InvalidMove(san) => write!(f, "cannot parse move '{}'", san),
+            SanError::IllegalMove(san) => write!(f, "illegal move '{}'", san),
+            SanError::AmbiguousMove(san) => write!(f, "ambiguous move '{}'", san),
         }
-        n_row += vec[0];
-        n_col += vec[1];
     }
-    result
 }
 
-pub fn legal_moves(board: &Board, row: usize, col: usize) -> HashSet<(usize, usize)> {
-    let moves = potential_moves(board, row, col);
-    let current_color = match board.squares[row][col] {
-        None => return HashSet::new(),
-        Some(piece) => piece.color,
+impl std::error::Error for SanError {}
+
+// every square the piece on `from` can reach by the way it moves, whether or not that leaves its king in check,
+// castling is not included
+pub fn legal_moves(board: &Board, from: Square) -> HashSet<Square> {
+    let Some(piece) = board.piece_at(from) else {
+        return HashSet::new();
     };
-    moves.iter()
-        .filter_map(|&(r, c)| {
-            match board.squares[r][c] {
-                None => Some((r, c)),
-                Some(dest_piece) => (dest_piece.color != current_color).then_some((r, c))
-            }
-        })
+    squares(Position::from_board(board, piece.color).pseudo_legal_targets(square_index(from)))
+}
+
+fn squares(bitboard: Bitboard) -> HashSet<Square> {
+    squares_of(bitboard).map(square_coordinates).collect()
+}
+
+// every legal move of the piece on `from`, a promotion is listed once per piece the pawn can become
+pub fn allowed_moves(board: &Board, from: Square, color: Color) -> Vec<Move> {
+    if !board.piece_at(from).is_some_and(|piece| piece.color == color) {
+        return Vec::new();
+    }
+    Position::from_board(board, color).legal_moves_from(square_index(from)).iter()
+        .filter_map(|m| board.create_move(m.move_from(), m.move_to(), m.promotion))
         .collect()
 }
 
-fn castle_rooks(color: Color) -> ((usize, usize), (usize, usize)) {
-    match color {
-        White => ((0, 0), (0, 7)),
-        Black => ((7, 0), (7, 7)),
+// `allowed_moves` quietly returns nothing for an empty square or a piece of the other colour, this says which it was
+pub fn try_allowed_moves(board: &Board, from: Square, color: Color) -> Result<Vec<Move>, ChessError> {
+    Square::new(from.0, from.1)?;
+    let piece = board.piece_at(from).ok_or(ChessError::EmptySquare(from))?;
+    if piece.color != color {
+        return Err(ChessError::WrongColor(from));
     }
+    if !board.has_king(color) {
+        return Err(ChessError::MissingKing(color));
+    }
+    Ok(allowed_moves(board, from, color))
 }
 
-fn potential_moves(board: &Board, row: usize, col: usize) -> HashSet<(usize, usize)> {
-    let i_row = row as i8;
-    let i_col = col as i8;
-    match board.squares[row][col] {
-        None => HashSet::new(),
-        Some(p) => match p.kind {
-            PieceType::King => {
-                let result = [move_straight(board, row, col), move_diagonally(board, row, col)].concat();
-                result.into_iter()
-                    .map(|(r, c)| (r as i8, c as i8))
-                    .filter(|(r, c)| (i_row - r).abs() <= 1 && (i_col - c).abs() <= 1 )
-                    .map(|(r, c)| (r as usize, c as usize))
-                    .collect()
-            }
-            PieceType::Queen => [move_straight(board, row, col), move_diagonally(board, row, col)].concat().iter().copied().collect(),
-            PieceType::Rook => move_straight(board, row, col).iter().copied().collect(),
-            PieceType::Bishop => move_diagonally(board, row, col).iter().copied().collect(),
-            PieceType::Knight => {
-                let potential_moves = [
-                    (i_row + 2, i_col + 1), (i_row + 1, i_col + 2), (i_row - 1, i_col + 2), (i_row - 2, i_col + 1),
-                    (i_row - 2, i_col - 1), (i_row - 1, i_col - 2), (i_row + 1, i_col - 2), (i_row + 2, i_col - 1)
-                ];
-                potential_moves.iter()
-                    .filter(|(r, c)| *r >= 0 && *c >= 0 && *r < HEIGHT as i8 && *c < WIDTH as i8)
-                    .map(|&(r, c)| (r as usize, c as usize))
-                    .collect()
-            }
-            PieceType::Pawn => {
-                let (move_one, move_two, start_rank) = match p.color {
-                    Color::White => (i_row + 1, i_row + 2, START_RANK_WHITE),
-                    Color::Black => (i_row - 1, i_row - 2, START_RANK_BLACK)
-                };
-                let moves_forward =
-                    if row == start_rank && move_two < HEIGHT as i8 && board.squares[move_one as usize][col].is_none() && board.squares[move_two as usize][col].is_none() {
-                        vec!((move_one as usize, col), (move_two as usize, col))
-                    }
-                    else if move_one < HEIGHT as i8 && board.squares[move_one as usize][col].is_none() {
-                        vec!((move_one as usize, col))
-                    }
-                    else {
-                        Vec::new()
-                    };
-                let moves_capturing: Vec<(usize, usize)> = [(move_one, i_col - 1), (move_one, i_col + 1)].iter()
-                    .filter(|(r, c)| *r >= 0 && *c >= 0 && *r < HEIGHT as i8 && *c < WIDTH as i8)
-                    .map(|(r, c)| (*r as usize, *c as usize))
-                    .filter(|(r, c)| match board.squares[*r][*c] {
-                        None => {
-                            match board.move_history.last() {
-                                None => false,
-                                Some(&(last_piece, last_start, last_stop)) =>
-                                    last_piece.kind == PieceType::Pawn &&
-                                        last_piece.color != p.color &&
-                                        (last_start.0 == START_RANK_BLACK || last_start.0 == START_RANK_WHITE) &&
-                                        last_start.1 == *c && last_stop.0 == row &&
-                                        (last_start.0 + 2 == last_stop.0 || last_stop.0 + 2 == last_start.0)
-                            }
-                        },
-                        Some(_) => true
-                    })
-                    .collect();
-
-                [moves_forward, moves_capturing].concat().iter().copied().collect()
-            }
-        }
+// `game_result` for a board which may have lost a king, no result means anything without both of them
+pub fn try_game_result(board: &Board) -> Result<GameStatus, ChessError> {
+    if let Some(missing) = [White, Black].into_iter().find(|&color| !board.has_king(color)) {
+        return Err(ChessError::MissingKing(missing));
     }
+    Ok(game_result(board))
 }
 
-pub fn allowed_moves(board: &Board, row: usize, col: usize, color: Color) -> HashSet<(usize, usize)> {
-    let (moves, moving_piece) = match board.squares[row][col] {
-        None => (HashSet::new(), PieceType::Pawn),
-        Some(piece) =>
-            if piece.color != color {
-                (HashSet::new(), PieceType::Pawn)
-            }
-            else {
-                match piece.kind {
-                    PieceType::King => {
-                        let mut moves = legal_moves(board, row, col);
-                        let under_attack = &all_potential_attacks(board)[&piece.color.opposite()];
-                        if !board.move_history.iter().any(|&(p, _, _)| piece == p) {
-                            let (long_castle, short_castle) = castle_rooks(piece.color);
-                            if !board.move_history.iter().any(|&(_, from, _)| from == long_castle) {
-                                if board.squares[row][col - 1].is_none() &&
-                                    board.squares[row][col - 2].is_none() &&
-                                    ![(row, col), (row, col - 1)].iter().any(|x| under_attack.contains(x)) {
-                                    moves.insert((row, col - 2));
-                                }
-                            }
-                            if !board.move_history.iter().any(|&(_, from, _)| from == short_castle) {
-                                if board.squares[row][col + 1].is_none() &&
-                                    board.squares[row][col + 2].is_none() &&
-                                    ![(row, col), (row, col + 1)].iter().any(|x| under_attack.contains(x)) {
-                                    moves.insert((row, col + 2));
-                                }
-                            }
-                        }
-                        (moves, piece.kind)
-                    },
-                    _ => (legal_moves(board, row, col), piece.kind)
-                }
-            }
-    };
+// every legal move of `color` generated in one pass over the position, promotions are listed like in `allowed_moves`
+pub fn legal_moves_for(board: &Board, color: Color) -> Vec<Move> {
+    Position::from_board(board, color).legal_moves().iter()
+        .filter_map(|m| board.create_move(m.move_from(), m.move_to(), m.promotion))
+        .collect()
+}
 
-    moves.iter().filter(|&&(r, c)| {
-        let mut new_board = board.clone();
-        new_board.make_move((row, col), (r, c));
-        !all_potential_attacks(&new_board)[&color.opposite()].contains(&new_board.king_positions[&color])
-    }).copied().collect()
+pub fn legal_move_count(board: &Board, color: Color) -> usize {
+    Position::from_board(board, color).legal_moves().len()
 }
 
-fn filter_moves_by_color(board: &Board, occupied_squares: &Vec<(Color, usize, usize)>, to_find: Color, only_attacks: bool) -> HashSet<(usize, usize)> {
-    let one_color: Vec<(usize, usize)> = occupied_squares.iter()
-        .filter_map(|&(color, r, c)| (color == to_find).then_some((r, c)))
-        .collect();
-    let attacks: HashSet<(usize, usize)> = one_color.iter()
-        .filter(|&&(r, c)| !only_attacks || board.squares[r][c].expect("Must be present").kind != PieceType::Pawn)
-        .flat_map(|&(row, col)| potential_moves(board, row, col))
-        .collect();
-    let pawn_attacks: HashSet<(usize, usize)> = one_color.iter()
-        .filter(|&&(r, c)| board.squares[r][c].expect("Must be present").kind == PieceType::Pawn)
-        .flat_map(|&(row, col)| {
-            let new_row: i8 = match to_find {
-                White => row as i8 + 1,
-                Black => row as i8 - 1,
-            };
-            let attacked_squares: HashSet<(usize, usize)> = [(new_row, col as i8 - 1), (new_row, col as i8 + 1)].iter()
-                .filter_map(|&(r, c)| (c >= 0 && c < WIDTH as i8).then_some((r as usize, c as usize)) )
-                .collect();
-            attacked_squares
-        })
-        .collect();
-    &attacks | &pawn_attacks
+// stops at the first legal move found
+pub fn has_any_legal_move(board: &Board, color: Color) -> bool {
+    Position::from_board(board, color).has_legal_move()
 }
 
-fn all_attacks_moves(board: &Board, only_attacks: bool) -> HashMap<Color, HashSet<(usize, usize)>> {
-    let occupied_squares: Vec<(Color, usize, usize)> = (0..HEIGHT).into_iter()
-        .flat_map(|r| (0..WIDTH).into_iter().map(move |c| (r, c)))
-        .filter(|(r, c)| board.squares[*r][*c].is_some())
-        .map(|(r, c)| (board.squares[r][c].unwrap().color, r, c))
-        .collect();
+pub fn allowed_promotions(board: &Board, move_from: Square, move_to: Square, color: Color) -> Vec<PieceType> {
+    allowed_moves(board, move_from, color).iter()
+        .filter(|m| m.to == move_to)
+        .filter_map(|m| m.promotion())
+        .collect()
+}
+
+pub fn all_potential_attacks(board: &Board) -> HashMap<Color, HashSet<Square>> {
+    let position = Position::from_board(board, board.color_to_play());
     HashMap::from([
-        (White, filter_moves_by_color(board, &occupied_squares, White, only_attacks)),
-        (Black, filter_moves_by_color(board, &occupied_squares, Black, only_attacks)),
+        (White, squares(position.attacks(White))),
+        (Black, squares(position.attacks(Black))),
     ])
 }
 
-pub fn all_potential_attacks(board: &Board) -> HashMap<Color, HashSet<(usize, usize)>> {
-    all_attacks_moves(board, true)
+// the attacked squares plus wherever a pawn could step forward
+pub fn all_potential_moves(board: &Board) -> HashMap<Color, HashSet<Square>> {
+    [White, Black].into_iter()
+        .map(|color| {
+            let position = Position::from_board(board, color);
+            let pawn_moves = squares_of(position.pieces(color, PieceType::Pawn))
+                .fold(0, |moves, square| moves | position.pseudo_legal_targets(square));
+            (color, squares(position.attacks(color) | pawn_moves))
+        })
+        .collect()
 }
 
-pub fn all_potential_moves(board: &Board) -> HashMap<Color, HashSet<(usize, usize)>> {
-    all_attacks_moves(board, false)
+pub fn game_result(board: &Board) -> GameStatus {
+    if board.repetition_count() >= 3 {
+       return  GameStatus::Draw(DrawReason::ThreefoldRepetition);
+    }
+
+    // the side which has just moved cannot be in check, so only the side to move can be mated or stalemated
+    let result = check_mate(board, board.color_to_play());
+    // a checkmate delivered on the last allowed move still counts
+    if result != GameStatus::InProgress {
+        result
+    }
+    else if insufficient_material(board) {
+        GameStatus::Draw(DrawReason::InsufficientMaterial)
+    }
+    else if board.halfmove_clock >= SEVENTY_FIVE_MOVE_RULE_PLIES {
+        GameStatus::Draw(DrawReason::SeventyFiveMoveRule)
+    }
+    else {
+        result
+    }
 }
 
-pub fn game_result(board: &Board) -> GameStatus {
-    if board.max_position_count == 3 {
-       return  GameStatus::Draw;
+// covers dead positions where no sequence of moves can lead to a checkmate:
+// lone kings, a single minor piece, or only bishops all standing on the same square colour
+pub fn insufficient_material(board: &Board) -> bool {
+    let pieces: Vec<(PieceType, usize, usize)> = (0..HEIGHT)
+        .flat_map(|r| (0..WIDTH).map(move |c| (r, c)))
+        .filter_map(|(r, c)| board.squares[r][c].map(|p| (p.kind, r, c)))
+        .filter(|&(kind, _, _)| kind != PieceType::King)
+        .collect();
+    match pieces.as_slice() {
+        [] => true,
+        [(PieceType::Knight, _, _)] | [(PieceType::Bishop, _, _)] => true,
+        [(_, first_row, first_col), ..] => pieces.iter()
+            .all(|&(kind, r, c)| kind == PieceType::Bishop && (r + c) % 2 == (first_row + first_col) % 2),
     }
+}
+
+// a side left with a bare king cannot win, so running out of time against it is a draw
+pub fn has_mating_material(board: &Board, color: Color) -> bool {
+    !insufficient_material(board) && board.squares.iter().flatten().flatten().any(|p| p.color == color && p.kind != PieceType::King)
+}
 
-    let white_result = check_mate(board, &White);
-    if white_result == GameStatus::InProgress {
-        check_mate(board, &Black)
+pub fn claimable_draw(board: &Board) -> Option<DrawReason> {
+    (game_result(board) == GameStatus::InProgress && board.halfmove_clock >= FIFTY_MOVE_RULE_PLIES)
+        .then_some(DrawReason::FiftyMoveRule)
+}
+
+fn check_mate(board: &Board, color: Color) -> GameStatus {
+    if has_any_legal_move(board, color) {
+        GameStatus::InProgress
+    }
+    else if Position::from_board(board, color).in_check(color) {
+        GameStatus::Win(color.opposite(), WinReason::Checkmate)
     }
     else {
-        white_result
+        GameStatus::Draw(DrawReason::Stalemate)
     }
 }
 
-fn check_mate(board: &Board, color: &Color) -> GameStatus {
-    let king = board.king_positions[color];
-    if all_potential_attacks(board)[&color.opposite()].contains(&king) {
-        if (0..HEIGHT)
-            .flat_map(|col| (0..WIDTH).map(move |row| (row, col)))
-            .filter(|&(r, c)| board.squares[r][c].is_some_and(|x| x.color == *color))
-            .flat_map(|(r, c)| allowed_moves(board, r, c, *color).into_iter().map(move |(nr, nc)| ((r, c), (nr, nc))))
-            .any(|((move_from), (move_to))| {
-                let mut new_board = board.clone();
-                new_board.make_move(move_from, move_to);
-                !all_potential_attacks(&new_board)[&color.opposite()].contains(&new_board.king_positions[&color])
-            }) {
-            GameStatus::InProgress
-        }
-        else {
-            GameStatus::Win(color.opposite())
+fn piece_kind(letter: char) -> Option<PieceType> {
+    match letter {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'R' => Some(PieceType::Rook),
+        'B' => Some(PieceType::Bishop),
+        'N' => Some(PieceType::Knight),
+        _ => None,
+    }
+}
+
+fn piece_letter(kind: PieceType) -> char {
+    Piece { color: White, kind }.to_char()
+}
+
+pub fn from_san(board: &Board, san: &str) -> Result<Move, SanError> {
+    let color = board.color_to_play();
+    let invalid = || SanError::InvalidMove(san.to_string());
+    let body = san.trim_end_matches(['+', '#']);
+
+    if matches!(body, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+        let kind = if body.len() == 3 { MoveKind::CastleKingSide } else { MoveKind::CastleQueenSide };
+        return legal_moves_for(board, color).into_iter()
+            .find(|m| m.kind == kind)
+            .ok_or_else(|| SanError::IllegalMove(san.to_string()));
+    }
+
+    let (body, promotion) = match body.char_indices().last() {
+        Some((i, c)) if piece_kind(c).is_some() && i > 0 => {
+            let kind = piece_kind(c);
+            (body[..i].trim_end_matches('='), kind)
         }
+        _ => (body, None),
+    };
+    let (kind, body) = match body.chars().next().and_then(piece_kind) {
+        Some(kind) => (kind, &body[1..]),
+        None => (PieceType::Pawn, body),
+    };
+    if body.len() < 2 || !body.is_char_boundary(body.len() - 2) {
+        return Err(invalid());
     }
-    else if (0..HEIGHT)
-        .flat_map(|r| (0..WIDTH).map(move |c| (r, c)))
-        .any(|(r, c)| !allowed_moves(board, r, c, *color).is_empty()) {
-        GameStatus::InProgress
+    let move_to = Square::parse(&body[body.len() - 2..]).ok_or_else(invalid)?;
+    let hint: Vec<char> = body[..body.len() - 2].chars().filter(|&c| c != 'x').collect();
+    let (hint_col, hint_row) = match hint.as_slice() {
+        [] => (None, None),
+        [c] if c.is_ascii_lowercase() => (Some(*c), None),
+        [r] => (None, Some(*r)),
+        [c, r] => (Some(*c), Some(*r)),
+        _ => return Err(invalid()),
+    };
+    let hint_col = match hint_col {
+        None => None,
+        Some(c) => Some(Square::parse(&format!("{}1", c)).ok_or_else(invalid)?.col()),
+    };
+    let hint_row = match hint_row {
+        None => None,
+        Some(r) => Some(Square::parse(&format!("a{}", r)).ok_or_else(invalid)?.row()),
+    };
+
+    let last_rank = if color == White { HEIGHT - 1 } else { 0 };
+    let promotion = match promotion {
+        Some(PieceType::King) => return Err(invalid()),
+        Some(_) if kind != PieceType::Pawn || move_to.row() != last_rank => return Err(invalid()),
+        None if kind == PieceType::Pawn && move_to.row() == last_rank => Some(PieceType::Queen),
+        p => p,
+    };
+
+    let candidates: Vec<Move> = legal_moves_for(board, color).into_iter()
+        .filter(|m| m.piece == Piece { color, kind } && m.to == move_to && m.promotion() == promotion)
+        .filter(|m| hint_row.is_none_or(|x| x == m.from.row()) && hint_col.is_none_or(|x| x == m.from.col()))
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(SanError::IllegalMove(san.to_string())),
+        [m] => Ok(*m),
+        _ => Err(SanError::AmbiguousMove(san.to_string())),
+    }
+}
+
+pub fn to_san(board: &Board, m: &Move) -> String {
+    let piece = m.piece;
+    let mut san = String::new();
+    if m.is_castling() {
+        san.push_str(if m.to.col() > m.from.col() { "O-O" } else { "O-O-O" });
+    }
+    else if piece.kind == PieceType::Pawn {
+        if m.is_capture() {
+            san.push_str(&m.from.to_string()[..1]);
+            san.push('x');
+        }
+        san.push_str(&m.to.to_string());
+        if let Some(kind) = m.promotion() {
+            san.push('=');
+            san.push(piece_letter(kind));
+        }
     }
     else {
-        GameStatus::Draw
+        san.push(piece_letter(piece.kind));
+        let others: Vec<Square> = legal_moves_for(board, piece.color).iter()
+            .filter(|other| other.from != m.from && other.piece == piece && other.to == m.to)
+            .map(|other| other.from)
+            .collect();
+        let from_name = m.from.to_string();
+        if !others.is_empty() {
+            if others.iter().all(|s| s.col() != m.from.col()) {
+                san.push_str(&from_name[..1]);
+            }
+            else if others.iter().all(|s| s.row() != m.from.row()) {
+                san.push_str(&from_name[1..]);
+            }
+            else {
+                san.push_str(&from_name);
+            }
+        }
+        if m.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&m.to.to_string());
+    }
+
+    let mut next = board.clone();
+    next.make_move(*m);
+    let opponent = piece.color.opposite();
+    if Position::from_board(&next, opponent).in_check(opponent) {
+        san.push(if has_any_legal_move(&next, opponent) { '+' } else { '#' });
     }
+    san
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::{HashMap, HashSet};
-    use crate::board::{Board, Color, HEIGHT, new_board, Piece, PieceType, WIDTH, GameStatus, to_string};
+    use crate::board::{Board, CastlingRights, ChessError, Checks, Color, DrawReason, HEIGHT, Move, new_board, Piece, PieceType, Square, WIDTH, GameStatus, WinReason};
     use crate::board::PieceType::Pawn;
-    use crate::Color::{Black, White};
-    use crate::moves::{legal_moves, all_potential_attacks, allowed_moves, all_potential_moves, game_result};
+    use crate::board::Color::{Black, White};
+    use crate::moves::{legal_moves, all_potential_attacks, allowed_moves, all_potential_moves, game_result, claimable_draw, insufficient_material, has_mating_material, from_san, to_san, allowed_promotions, SanError, legal_moves_for, legal_move_count, has_any_legal_move, try_allowed_moves, try_game_result};
+    use crate::variant::GameVariant;
 
     fn board_one_piece(row: usize, col: usize, color: Color, kind: PieceType) -> Board {
         let mut board = Board{
             squares: [[None; WIDTH]; HEIGHT],
             move_history: Vec::new(),
-            king_positions: if kind == PieceType::King { HashMap::from([(color, (row, col))]) } else { HashMap::new() },
+            king_positions: if kind == PieceType::King { HashMap::from([(color, Square(row, col))]) } else { HashMap::new() },
+            side_to_move: color,
+            castling_rights: CastlingRights::default(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
             game_over: false,
             name: "Room".to_string(),
+            variant: GameVariant::Standard,
+            checks: Checks::default(),
             position_counter: HashMap::new(),
-            max_position_count: 1,
+            hash: 0,
+            undo_history: Vec::new(),
         };
         board.squares[row][col] = Some(Piece {color, kind});
         board
     }
 
+    fn targets(moves: Vec<Move>) -> HashSet<Square> {
+        moves.into_iter().map(|m| m.to).collect()
+    }
+
+    fn san(board: &Board, uci: &str) -> String {
+        to_san(board, &board.parse_move(uci).unwrap())
+    }
+
     #[test]
     fn test_threefold_repetition_draw() {
         let mut board = new_board();
-        board.make_move((0, 1), (2, 0));
+        board.make_move(board.parse_move("b1a3").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((7, 1), (5, 0));
+        board.make_move(board.parse_move("b8a6").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((2, 0), (0, 1));
+        board.make_move(board.parse_move("a3b1").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((5, 0), (7, 1));
+        board.make_move(board.parse_move("a6b8").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((0, 1), (2, 0));
+        board.make_move(board.parse_move("b1a3").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((7, 1), (5, 0));
+        board.make_move(board.parse_move("b8a6").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((2, 0), (0, 1));
+        board.make_move(board.parse_move("a3b1").unwrap());
         assert_eq!(game_result(&board), GameStatus::InProgress);
-        board.make_move((5, 0), (7, 1));
-        assert_eq!(game_result(&board), GameStatus::Draw);
+        board.make_move(board.parse_move("a6b8").unwrap());
+        assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::ThreefoldRepetition));
     }
 
     #[test]
     fn test_empty_squares() {
         let board = board_one_piece(0, 0, Color::White, PieceType::King);
-        let empty_moves = legal_moves(&board, 1, 1);
+        let empty_moves = legal_moves(&board, Square(1, 1));
         assert_eq!(empty_moves, HashSet::new());
     }
 
@@ -327,27 +382,38 @@ mod test {
         board.squares[7][5] = Some(Piece {color: Black, kind: PieceType::Rook});
         board.squares[6][6] = Some(Piece {color: Black, kind: PieceType::King});
         board.squares[6][4] = Some(Piece {color: White, kind: PieceType::King});
-        board.king_positions = HashMap::from([(Color::White, (6, 4)), (Black, (6, 6))]);
+        board.king_positions = HashMap::from([(Color::White, Square(6, 4)), (Black, Square(6, 6))]);
         let result = game_result(&board);
         assert_eq!(result, GameStatus::InProgress);
     }
 
+    #[test]
+    fn test_only_side_to_move_is_judged() {
+        // black would have no move, but it is white's turn
+        let board = Board::from_fen("k7/2Q5/1K6/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+        let board = Board::from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::Stalemate));
+        let board = Board::from_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game_result(&board), GameStatus::Win(White, WinReason::Checkmate));
+    }
+
     #[test]
     fn test_en_passant() {
         let mut board = board_one_piece(3, 4, White, PieceType::Pawn);
         board.squares[0][0] = Some( Piece {color: White, kind: PieceType::King});
         board.squares[7][7] = Some( Piece {color: Black, kind: PieceType::King});
-        board.king_positions = HashMap::from([(White, (0, 0)), (Black, (7, 7))]);
-        board.move_history.push((Piece { color: White, kind: PieceType::Pawn}, (1, 4), (3, 4)));
+        board.king_positions = HashMap::from([(White, Square(0, 0)), (Black, Square(7, 7))]);
+        board.en_passant = Some(Square(2, 4));
         board.squares[3][6] = Some(Piece { color: White, kind: PieceType::Pawn});
         board.squares[3][5] = Some(Piece { color: Black, kind: PieceType::Pawn});
-        let moves = allowed_moves(&board, 3, 5, Black);
-        assert_eq!(moves, HashSet::from([(2, 5), (2, 4)]));
+        let moves = targets(allowed_moves(&board, Square(3, 5), Black));
+        assert_eq!(moves, HashSet::from([Square(2, 5), Square(2, 4)]));
 
-        board.make_move((3, 5), (2, 4));
+        board.make_move(board.parse_move("f4e3").unwrap());
 
-        let count = (0 .. HEIGHT).into_iter()
-            .flat_map(|c| (0..WIDTH).into_iter().map(move |r| (r, c)))
+        let count = (0 .. HEIGHT)
+            .flat_map(|c| (0..WIDTH).map(move |r| (r, c)))
             .filter(|&(r, c)| board.squares[r][c].is_some())
             .count();
         assert_eq!(count, 4);
@@ -358,24 +424,23 @@ mod test {
         board.squares[6][5] = Some(Piece { color: Black, kind: PieceType::Pawn});
         board.squares[0][0] = Some( Piece {color: White, kind: PieceType::King});
         board.squares[7][7] = Some( Piece {color: Black, kind: PieceType::King});
-        board.king_positions = HashMap::from([(White, (0, 0)), (Black, (7, 7))]);
-        board.move_history.push((Piece { color: Black, kind: PieceType::Pawn}, (6, 3), (5, 3)));
-        let moves = allowed_moves(&board, 5, 4, White);
-        assert_eq!(moves, HashSet::from([(6, 5)]));
+        board.king_positions = HashMap::from([(White, Square(0, 0)), (Black, Square(7, 7))]);
+        let moves = targets(allowed_moves(&board, Square(5, 4), White));
+        assert_eq!(moves, HashSet::from([Square(6, 5)]));
 
         let mut board = new_board();
-        board.make_move((1, 3), (3, 3));
-        board.make_move((6, 4), (4, 4));
-        board.make_move((0, 3), (2, 3));
-        board.make_move((4, 4), (3, 4));
-        board.make_move((1, 7), (2, 7));
-        board.make_move((3, 4), (2, 3));
-        board.make_move((1, 2), (2, 3));
-        board.make_move((6, 2), (4, 2));
-        board.make_move((2, 7), (3, 7));
-        board.make_move((4, 2), (3, 2));
-        board.make_move((3, 7), (4, 7));
-        board.make_move((3, 2), (2, 3));
+        board.make_move(board.parse_move("d2d4").unwrap());
+        board.make_move(board.parse_move("e7e5").unwrap());
+        board.make_move(board.parse_move("d1d3").unwrap());
+        board.make_move(board.parse_move("e5e4").unwrap());
+        board.make_move(board.parse_move("h2h3").unwrap());
+        board.make_move(board.parse_move("e4d3").unwrap());
+        board.make_move(board.parse_move("c2d3").unwrap());
+        board.make_move(board.parse_move("c7c5").unwrap());
+        board.make_move(board.parse_move("h3h4").unwrap());
+        board.make_move(board.parse_move("c5c4").unwrap());
+        board.make_move(board.parse_move("h4h5").unwrap());
+        board.make_move(board.parse_move("c4d3").unwrap());
         let pawns = (0..HEIGHT)
             .flat_map(|r| (0..WIDTH).map(move |c| (r, c)))
             .filter(|&(r, c)| board.squares[r][c].is_some_and(|x| x.color == White && x.kind == Pawn))
@@ -389,10 +454,10 @@ mod test {
         board.squares[3][0] = Some(Piece {color: Black, kind: PieceType::Pawn});
         board.squares[3][2] = Some(Piece {color: Black, kind: PieceType::Pawn});
         let all_moves = all_potential_moves(&board);
-        assert_eq!(all_moves[&White], HashSet::from([(3, 0), (3, 1), (3, 2)]));
+        assert_eq!(all_moves[&White], HashSet::from([Square(3, 0), Square(3, 1), Square(3, 2)]));
 
         let all_attacks = all_potential_attacks(&board);
-        assert_eq!(all_attacks[&White], HashSet::from([(3, 0), (3, 2)]));
+        assert_eq!(all_attacks[&White], HashSet::from([Square(3, 0), Square(3, 2)]));
     }
 
     #[test]
@@ -400,19 +465,88 @@ mod test {
         let mut board = board_one_piece(0, 4, Color::White, PieceType::King);
         board.squares[0][0] = Some(Piece {color: White, kind: PieceType::Rook});
         board.squares[0][7] = Some(Piece {color: White, kind: PieceType::Rook});
-        let moves = allowed_moves(&board, 0, 4, White);
-        assert_eq!(moves.contains(&(0, 2)), true);
-        assert_eq!(moves.contains(&(0, 6)), true);
+        board.castling_rights = CastlingRights::all();
+        let moves = targets(allowed_moves(&board, Square(0, 4), White));
+        assert!(moves.contains(&Square(0, 2)));
+        assert!(moves.contains(&Square(0, 6)));
 
         board.squares[7][5] = Some(Piece {color: Black, kind: PieceType::Rook});
-        let moves = allowed_moves(&board, 0, 4, White);
-        assert_eq!(moves.contains(&(0, 2)), true);
-        assert_eq!(moves.contains(&(0, 6)), false);
+        let moves = targets(allowed_moves(&board, Square(0, 4), White));
+        assert!(moves.contains(&Square(0, 2)));
+        assert!(!moves.contains(&Square(0, 6)));
 
         board.squares[7][4] = Some(Piece {color: Black, kind: PieceType::Rook});
-        let moves = allowed_moves(&board, 0, 4, White);
-        assert_eq!(moves.contains(&(0, 2)), false);
-        assert_eq!(moves.contains(&(0, 6)), false);
+        let moves = targets(allowed_moves(&board, Square(0, 4), White));
+        assert!(!moves.contains(&Square(0, 2)));
+        assert!(!moves.contains(&Square(0, 6)));
+    }
+
+    // (king side, queen side) castling available to the side to move
+    fn castling_options(board: &Board) -> (bool, bool) {
+        (from_san(board, "O-O").is_ok(), from_san(board, "O-O-O").is_ok())
+    }
+
+    #[test]
+    fn test_castling_needs_empty_b_file() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1").unwrap();
+        assert_eq!(castling_options(&board), (true, false));
+        let board = Board::from_fen("rn2k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(castling_options(&board), (true, false));
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/Rn2K2R w KQkq - 0 1").unwrap();
+        assert_eq!(castling_options(&board), (true, false));
+
+        // the king does not cross b1, so an attack on it does not matter
+        let board = Board::from_fen("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(castling_options(&board), (true, true));
+    }
+
+    #[test]
+    fn test_castling_needs_rook() {
+        let mut board = board_one_piece(0, 4, White, PieceType::King);
+        board.castling_rights = CastlingRights::all();
+        assert_eq!(castling_options(&board), (false, false));
+
+        // another piece on the corner does not count either
+        board.squares[0][0] = Some(Piece {color: White, kind: PieceType::Queen});
+        board.squares[0][7] = Some(Piece {color: Black, kind: PieceType::Rook});
+        assert_eq!(castling_options(&board), (false, false));
+
+        // the rook taken on its corner takes that right with it
+        let mut board = Board::from_fen("4k3/8/8/8/8/6n1/8/R3K2R b KQ - 0 1").unwrap();
+        board.make_move(board.parse_move("g3h1").unwrap());
+        assert!(!board.castling_rights.white_king_side);
+        assert!(board.castling_rights.white_queen_side);
+        assert_eq!(castling_options(&board), (false, true));
+    }
+
+    #[test]
+    fn test_castling_rights_lost_after_moving() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        for m in ["a1a2", "e8d8", "a2a1", "d8e8"] {
+            board.make_move(board.parse_move(m).unwrap());
+        }
+        assert_eq!(castling_options(&board), (true, false));
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        for m in ["e1d1", "e8d8", "d1e1", "d8e8"] {
+            board.make_move(board.parse_move(m).unwrap());
+        }
+        assert_eq!(castling_options(&board), (false, false));
+    }
+
+    #[test]
+    fn test_castling_and_check() {
+        let cases = [
+            ("1k2r3/8/8/8/8/8/8/R3K2R w KQ - 0 1", (false, false)), // out of check
+            ("1k3r2/8/8/8/8/8/8/R3K2R w KQ - 0 1", (false, true)), // through f1
+            ("1k1r4/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, false)), // through d1
+            ("1k4r1/8/8/8/8/8/8/R3K2R w KQ - 0 1", (false, true)), // into g1
+            ("1kr5/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, false)), // into c1
+            ("1k6/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, true)),
+        ];
+        for (fen, expected) in cases {
+            assert_eq!(castling_options(&Board::from_fen(fen).unwrap()), expected, "{}", fen);
+        }
     }
 
     #[test]
@@ -420,8 +554,8 @@ mod test {
         let mut board = board_one_piece(4, 4, White, PieceType::King);
         board.squares[0][4] = Some(Piece {color: Black, kind: PieceType::Queen});
         board.squares[0][0] = Some(Piece {color: Black, kind: PieceType::Bishop});
-        let king = allowed_moves(&board, 4, 4, White);
-        assert_eq!(king, HashSet::from([(3, 5), (4, 3), (4, 5), (5, 3)]));
+        let king = targets(allowed_moves(&board, Square(4, 4), White));
+        assert_eq!(king, HashSet::from([Square(3, 5), Square(4, 3), Square(4, 5), Square(5, 3)]));
     }
 
     #[test]
@@ -430,112 +564,108 @@ mod test {
         board.squares[0][3] = Some(Piece{color: Black, kind: PieceType::Rook});
         board.squares[7][5] = Some(Piece{color: Black, kind: PieceType::Rook});
         board.squares[2][4] = Some(Piece{color: Black, kind: PieceType::King});
-        let actual_moves = allowed_moves(&board, 4, 4, White);
-        assert_eq!(actual_moves, HashSet::from([(5, 4)]));
+        let actual_moves = targets(allowed_moves(&board, Square(4, 4), White));
+        assert_eq!(actual_moves, HashSet::from([Square(5, 4)]));
 
         let mut board = board_one_piece(4, 4, White, PieceType::King);
-        board.move_history.push((Piece {color: White, kind: PieceType::King}, (0, 4), (0, 5)));
         board.squares[6][4] = Some(Piece{color: Black, kind: PieceType::Pawn});
-        let all_moves_black = &all_potential_attacks(&board)[&Black];
-        let actual_moves = allowed_moves(&board, 4, 4, White);
-        assert_eq!(actual_moves, HashSet::from([(3, 3), (3, 4), (3, 5), (4, 3), (4, 5), (5, 4)]));
+        let actual_moves = targets(allowed_moves(&board, Square(4, 4), White));
+        assert_eq!(actual_moves, HashSet::from([Square(3, 3), Square(3, 4), Square(3, 5), Square(4, 3), Square(4, 5), Square(5, 4)]));
 
         let mut board = board_one_piece(4, 4, White, PieceType::King);
         board.squares[5][4] = Some(Piece{color: Black, kind: PieceType::Queen});
         board.squares[7][3] = Some(Piece{color: Black, kind: PieceType::Knight});
         board.squares[3][3] = Some(Piece{color: Black, kind: PieceType::Knight});
         board.squares[4][2] = Some(Piece{color: Black, kind: PieceType::Pawn});
-        let all_moves_black = &all_potential_attacks(&board)[&Black];
-        let actual_moves = allowed_moves(&board, 4, 4, White);
-        assert_eq!(actual_moves, HashSet::from([(3, 5)]));
+        let actual_moves = targets(allowed_moves(&board, Square(4, 4), White));
+        assert_eq!(actual_moves, HashSet::from([Square(3, 5)]));
 
         let mut board = board_one_piece(3, 1, White, PieceType::King);
         board.squares[5][1] = Some(Piece{color: Black, kind: PieceType::Pawn});
-        board.move_history.push((board.squares[3][1].unwrap(), (2, 1), (3, 1)));
-        let actual_moves = allowed_moves(&board, 3, 1, White);
-        assert_eq!(actual_moves.contains(&(4, 0)), false);
+        let actual_moves = targets(allowed_moves(&board, Square(3, 1), White));
+        assert!(!actual_moves.contains(&Square(4, 0)));
     }
 
     #[test]
     fn test_king_moves() {
         let board = board_one_piece(0, 0, Color::White, PieceType::King);
-        let king_moves = legal_moves(&board, 0, 0);
-        assert_eq!(king_moves, HashSet::from([(0, 1), (1, 0), (1, 1)]));
+        let king_moves = legal_moves(&board, Square(0, 0));
+        assert_eq!(king_moves, HashSet::from([Square(0, 1), Square(1, 0), Square(1, 1)]));
 
         let board = board_one_piece(7, 7, Color::White, PieceType::King);
-        let king_moves = legal_moves(&board, 7, 7);
-        assert_eq!(king_moves, HashSet::from([(6, 6), (6, 7), (7, 6)]));
+        let king_moves = legal_moves(&board, Square(7, 7));
+        assert_eq!(king_moves, HashSet::from([Square(6, 6), Square(6, 7), Square(7, 6)]));
 
         let board = board_one_piece(3, 3, Color::White, PieceType::King);
-        let king_moves = legal_moves(&board, 3, 3);
-        assert_eq!(king_moves, HashSet::from([(2, 2), (2, 3), (2, 4), (3, 2), (3, 4), (4, 2), (4, 3), (4, 4)]));
+        let king_moves = legal_moves(&board, Square(3, 3));
+        assert_eq!(king_moves, HashSet::from([Square(2, 2), Square(2, 3), Square(2, 4), Square(3, 2), Square(3, 4), Square(4, 2), Square(4, 3), Square(4, 4)]));
     }
 
     #[test]
     fn test_rook_moves() {
         let board = board_one_piece(0, 0, Color::White, PieceType::Rook);
-        let moves = legal_moves(&board, 0, 0);
+        let moves = legal_moves(&board, Square(0, 0));
         assert_eq!(moves, HashSet::from([
-            (1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 0), (7, 0),
-            (0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (0, 6), (0, 7)
+            Square(1, 0), Square(2, 0), Square(3, 0), Square(4, 0), Square(5, 0), Square(6, 0), Square(7, 0),
+            Square(0, 1), Square(0, 2), Square(0, 3), Square(0, 4), Square(0, 5), Square(0, 6), Square(0, 7)
         ]));
 
         let board = new_board();
-        let moves = legal_moves(&board, 0, 0);
+        let moves = legal_moves(&board, Square(0, 0));
         assert_eq!(moves, HashSet::new());
 
         let mut board = new_board();
         board.squares[1][7] = None;
-        let moves = legal_moves(&board, 0, 7);
-        assert_eq!(moves, HashSet::from([(1, 7), (2, 7), (3, 7), (4, 7), (5, 7), (6, 7)]));
+        let moves = legal_moves(&board, Square(0, 7));
+        assert_eq!(moves, HashSet::from([Square(1, 7), Square(2, 7), Square(3, 7), Square(4, 7), Square(5, 7), Square(6, 7)]));
     }
 
     #[test]
     fn test_bishop_moves() {
         let board = board_one_piece(0, 0, Color::White, PieceType::Bishop);
-        let actual_moves = legal_moves(&board, 0, 0);
-        assert_eq!(actual_moves, HashSet::from([(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6), (7, 7)]));
+        let actual_moves = legal_moves(&board, Square(0, 0));
+        assert_eq!(actual_moves, HashSet::from([Square(1, 1), Square(2, 2), Square(3, 3), Square(4, 4), Square(5, 5), Square(6, 6), Square(7, 7)]));
 
         let board = board_one_piece(3, 3, Color::White, PieceType::Bishop);
-        let actual_moves = legal_moves(&board, 3, 3);
+        let actual_moves = legal_moves(&board, Square(3, 3));
         assert_eq!(actual_moves, HashSet::from([
-            (4, 4), (5, 5), (6, 6), (7, 7),
-            (2, 4), (1, 5), (0, 6),
-            (2, 2), (1, 1), (0, 0),
-            (4, 2), (5, 1), (6, 0)
+            Square(4, 4), Square(5, 5), Square(6, 6), Square(7, 7),
+            Square(2, 4), Square(1, 5), Square(0, 6),
+            Square(2, 2), Square(1, 1), Square(0, 0),
+            Square(4, 2), Square(5, 1), Square(6, 0)
         ]));
 
         let board = board_one_piece(5, 1, Color::White, PieceType::Bishop);
-        let actual_moves = legal_moves(&board, 5, 1);
+        let actual_moves = legal_moves(&board, Square(5, 1));
         assert_eq!(actual_moves, HashSet::from([
-            (6, 2), (7, 3),
-            (4, 2), (3, 3), (2, 4), (1, 5), (0, 6),
-            (4, 0),
-            (6, 0)
+            Square(6, 2), Square(7, 3),
+            Square(4, 2), Square(3, 3), Square(2, 4), Square(1, 5), Square(0, 6),
+            Square(4, 0),
+            Square(6, 0)
         ]));
 
         let board = new_board();
-        let actual_moves = legal_moves(&board, 0, 2);
+        let actual_moves = legal_moves(&board, Square(0, 2));
         assert_eq!(actual_moves, HashSet::new());
     }
 
     #[test]
     fn test_queen_moves() {
         let board = board_one_piece(4, 2, Color::White, PieceType::Queen);
-        let actual_moves = legal_moves(&board, 4, 2);
+        let actual_moves = legal_moves(&board, Square(4, 2));
         assert_eq!(actual_moves, HashSet::from([
-            (5, 2), (6, 2), (7, 2),
-            (3, 2), (2, 2), (1, 2), (0, 2),
-            (4, 3), (4, 4), (4, 5), (4, 6), (4, 7),
-            (4, 1), (4, 0),
-            (5, 3), (6, 4), (7, 5),
-            (3, 3), (2, 4), (1, 5), (0, 6),
-            (3, 1), (2, 0),
-            (5, 1), (6, 0)
+            Square(5, 2), Square(6, 2), Square(7, 2),
+            Square(3, 2), Square(2, 2), Square(1, 2), Square(0, 2),
+            Square(4, 3), Square(4, 4), Square(4, 5), Square(4, 6), Square(4, 7),
+            Square(4, 1), Square(4, 0),
+            Square(5, 3), Square(6, 4), Square(7, 5),
+            Square(3, 3), Square(2, 4), Square(1, 5), Square(0, 6),
+            Square(3, 1), Square(2, 0),
+            Square(5, 1), Square(6, 0)
         ]));
 
         let board = new_board();
-        let actual_moves = legal_moves(&board, 0, 3);
+        let actual_moves = legal_moves(&board, Square(0, 3));
         assert_eq!(actual_moves, HashSet::new());
 
         let mut board = new_board();
@@ -544,61 +674,340 @@ mod test {
         board.squares[1][4] = None;
         board.squares[4][7] = Some(Piece{color: White, kind: PieceType::Pawn});
         board.squares[3][0] = Some(Piece{color: Black, kind: PieceType::Pawn});
-        let actual_moves = legal_moves(&board, 0, 3);
+        let actual_moves = legal_moves(&board, Square(0, 3));
         assert_eq!(actual_moves, HashSet::from([
-            (1, 3), (2, 3), (3, 3), (4, 3), (5, 3), (6, 3),
-            (1, 4), (2, 5), (3, 6),
-            (1, 2), (2, 1), (3, 0)
+            Square(1, 3), Square(2, 3), Square(3, 3), Square(4, 3), Square(5, 3), Square(6, 3),
+            Square(1, 4), Square(2, 5), Square(3, 6),
+            Square(1, 2), Square(2, 1), Square(3, 0)
         ]));
     }
 
     #[test]
     fn test_knight_moves() {
         let board = new_board();
-        let actual_moves = legal_moves(&board, 0, 1);
-        assert_eq!(actual_moves, HashSet::from([(2, 0), (2, 2)]));
+        let actual_moves = legal_moves(&board, Square(0, 1));
+        assert_eq!(actual_moves, HashSet::from([Square(2, 0), Square(2, 2)]));
 
         let mut board = board_one_piece(7, 0, Color::White, PieceType::Knight);
         board.squares[5][1] = Some(Piece{color: Black, kind: PieceType::Queen});
-        let actual_moves = legal_moves(&board, 7, 0);
-        assert_eq!(actual_moves, HashSet::from([(6, 2), (5, 1)]));
+        let actual_moves = legal_moves(&board, Square(7, 0));
+        assert_eq!(actual_moves, HashSet::from([Square(6, 2), Square(5, 1)]));
 
         let board = board_one_piece(5, 5, Color::White, PieceType::Knight);
-        let actual_moves = legal_moves(&board, 5, 5);
+        let actual_moves = legal_moves(&board, Square(5, 5));
         assert_eq!(actual_moves, HashSet::from([
-            (7, 4), (7, 6), (4, 7), (6, 7),
-            (3, 4), (3, 6), (4, 3), (6, 3)
+            Square(7, 4), Square(7, 6), Square(4, 7), Square(6, 7),
+            Square(3, 4), Square(3, 6), Square(4, 3), Square(6, 3)
         ]));
     }
 
     #[test]
     fn test_pawn_moves() {
         let board = new_board();
-        let actual_moves = legal_moves(&board, 1, 0);
-        assert_eq!(actual_moves, HashSet::from([(2, 0), (3, 0)]));
+        let actual_moves = legal_moves(&board, Square(1, 0));
+        assert_eq!(actual_moves, HashSet::from([Square(2, 0), Square(3, 0)]));
 
-        let actual_moves = legal_moves(&board, 6, 6);
-        assert_eq!(actual_moves, HashSet::from([(5, 6), (4, 6)]));
+        let actual_moves = legal_moves(&board, Square(6, 6));
+        assert_eq!(actual_moves, HashSet::from([Square(5, 6), Square(4, 6)]));
 
         let board = board_one_piece(6, 1, Color::White, PieceType::Pawn);
-        let actual_moves = legal_moves(&board, 6, 1);
-        assert_eq!(actual_moves, HashSet::from([(7, 1)]));
+        let actual_moves = legal_moves(&board, Square(6, 1));
+        assert_eq!(actual_moves, HashSet::from([Square(7, 1)]));
 
         let board = board_one_piece(6, 1, Color::Black, PieceType::Pawn);
-        let actual_moves = legal_moves(&board, 6, 1);
-        assert_eq!(actual_moves, HashSet::from([(4, 1), (5, 1)]));
+        let actual_moves = legal_moves(&board, Square(6, 1));
+        assert_eq!(actual_moves, HashSet::from([Square(4, 1), Square(5, 1)]));
 
         let mut board = board_one_piece(3, 3, Color::Black, PieceType::Pawn);
         board.squares[2][2] = Some(Piece{color: White, kind: PieceType::Pawn});
         board.squares[2][3] = Some(Piece{color: White, kind: PieceType::Pawn});
         board.squares[2][4] = Some(Piece{color: White, kind: PieceType::Pawn});
-        let actual_moves = legal_moves(&board, 3, 3);
-        assert_eq!(actual_moves, HashSet::from([(2, 2), (2, 4)]));
+        let actual_moves = legal_moves(&board, Square(3, 3));
+        assert_eq!(actual_moves, HashSet::from([Square(2, 2), Square(2, 4)]));
 
         let mut board = board_one_piece(4, 4, Color::White, PieceType::Pawn);
         board.squares[4][3] = Some(Piece{color: Black, kind: PieceType::Pawn});
-        board.move_history = vec![(Piece{color: Black, kind: PieceType::Pawn}, (6, 3), (4, 3))];
-        let actual_moves = legal_moves(&board, 4, 4);
-        assert_eq!(actual_moves, HashSet::from([(5, 4), (5, 3)]));
+        board.en_passant = Some(Square(5, 3));
+        let actual_moves = legal_moves(&board, Square(4, 4));
+        assert_eq!(actual_moves, HashSet::from([Square(5, 4), Square(5, 3)]));
+    }
+
+    #[test]
+    fn test_to_san() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        assert_eq!(san(&board, "a1d1"), "Rad1");
+        assert_eq!(san(&board, "h1d1"), "Rhd1");
+        assert_eq!(san(&board, "a1a8"), "Ra8+");
+
+        let board = Board::from_fen("4k3/R7/8/8/8/8/4K3/R7 w - - 0 1").unwrap();
+        assert_eq!(san(&board, "a1a4"), "R1a4");
+        assert_eq!(san(&board, "a7a4"), "R7a4");
+
+        let board = Board::from_fen("4k3/8/8/8/8/Q1Q5/8/Q3K3 w - - 0 1").unwrap();
+        assert_eq!(san(&board, "a1b2"), "Q1b2");
+        assert_eq!(san(&board, "a3b2"), "Qa3b2");
+        assert_eq!(san(&board, "c3b2"), "Qcb2");
+
+        let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        assert_eq!(san(&board, "e5d6"), "exd6");
+        assert_eq!(san(&board, "e5e6"), "e6");
+
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(san(&board, "e1g1"), "O-O");
+        assert_eq!(san(&board, "e1c1"), "O-O-O");
+        assert_eq!(san(&board, "e5f7"), "Nxf7");
+        assert_eq!(san(&board, "f3h3"), "Qxh3");
+
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(san(&board, "b7b8"), "b8=Q+");
+        assert_eq!(san(&board, "b7b8n"), "b8=N");
+        assert_eq!(san(&board, "b7b8r"), "b8=R+");
+
+        let board = Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        assert_eq!(san(&board, "h5f7"), "Qxf7#");
+    }
+
+    #[test]
+    fn test_from_san() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let moves: Vec<Move> = (0..HEIGHT)
+            .flat_map(|r| (0..WIDTH).map(move |c| Square(r, c)))
+            .flat_map(|from| allowed_moves(&board, from, White))
+            .collect();
+        assert_eq!(moves.len(), 48);
+        for m in moves {
+            assert_eq!(from_san(&board, &to_san(&board, &m)), Ok(m));
+        }
+
+        let board = new_board();
+        assert_eq!(from_san(&board, "e4"), Ok(board.parse_move("e2e4").unwrap()));
+        assert_eq!(from_san(&board, "Nf3"), Ok(board.parse_move("g1f3").unwrap()));
+        assert_eq!(from_san(&board, "Ng1f3"), Ok(board.parse_move("g1f3").unwrap()));
+        assert_eq!(from_san(&board, "e5"), Err(SanError::IllegalMove("e5".to_string())));
+        assert_eq!(from_san(&board, "O-O"), Err(SanError::IllegalMove("O-O".to_string())));
+        assert_eq!(from_san(&board, "Nf"), Err(SanError::InvalidMove("Nf".to_string())));
+        assert_eq!(from_san(&board, "Nxyz3"), Err(SanError::InvalidMove("Nxyz3".to_string())));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        assert_eq!(from_san(&board, "Rd1"), Err(SanError::AmbiguousMove("Rd1".to_string())));
+        assert_eq!(from_san(&board, "Rhd1"), Ok(board.parse_move("h1d1").unwrap()));
+
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(from_san(&board, "b8=N"), Ok(board.parse_move("b7b8n").unwrap()));
+        assert_eq!(from_san(&board, "b8B"), Ok(board.parse_move("b7b8b").unwrap()));
+        assert_eq!(from_san(&board, "b8"), Ok(board.parse_move("b7b8q").unwrap()));
+        assert_eq!(from_san(&board, "b8=K"), Err(SanError::InvalidMove("b8=K".to_string())));
+    }
+
+    #[test]
+    fn test_try_allowed_moves() {
+        let board = new_board();
+        assert_eq!(try_allowed_moves(&board, Square(1, 4), White).unwrap().len(), 2);
+        assert_eq!(try_allowed_moves(&board, Square(3, 4), White), Err(ChessError::EmptySquare(Square(3, 4))));
+        assert_eq!(try_allowed_moves(&board, Square(6, 4), White), Err(ChessError::WrongColor(Square(6, 4))));
+        assert_eq!(try_allowed_moves(&board, Square(8, 0), White), Err(ChessError::OffBoard(8, 0)));
+        assert!(allowed_moves(&board, Square(8, 0), White).is_empty());
+
+        // a board put together by hand without a black king
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        board.squares[7][4] = None;
+        assert_eq!(try_allowed_moves(&board, Square(0, 4), White).unwrap().len(), 4);
+        assert_eq!(try_allowed_moves(&board, Square(1, 4), Black), Err(ChessError::WrongColor(Square(1, 4))));
+        assert_eq!(try_game_result(&board), Err(ChessError::MissingKing(Black)));
+        board.squares[0][4] = None;
+        assert_eq!(try_allowed_moves(&board, Square(1, 4), White), Err(ChessError::MissingKing(White)));
+        assert_eq!(try_game_result(&board), Err(ChessError::MissingKing(White)));
+        assert_eq!(try_game_result(&new_board()), Ok(GameStatus::InProgress));
+    }
+
+    #[test]
+    fn test_legal_moves_for() {
+        let board = new_board();
+        assert_eq!(legal_moves_for(&board, White).len(), 20);
+        assert_eq!(legal_move_count(&board, Black), 20);
+
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let per_square: HashSet<Move> = (0..HEIGHT)
+            .flat_map(|r| (0..WIDTH).map(move |c| Square(r, c)))
+            .flat_map(|from| allowed_moves(&board, from, White))
+            .collect();
+        let moves = legal_moves_for(&board, White);
+        assert_eq!(moves.len(), 48);
+        assert_eq!(moves.iter().copied().collect::<HashSet<Move>>(), per_square);
+        assert_eq!(legal_move_count(&board, White), 48);
+
+        // promotions are listed once per piece
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(legal_moves_for(&board, White).iter().filter(|m| m.promotion().is_some()).count(), 4);
+
+        let stalemate = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(!has_any_legal_move(&stalemate, Black));
+        assert!(has_any_legal_move(&stalemate, White));
+        assert_eq!(legal_moves_for(&stalemate, Black), Vec::new());
+        assert_eq!(game_result(&stalemate), GameStatus::Draw(DrawReason::Stalemate));
+    }
+
+    #[test]
+    fn test_promotion() {
+        let board = Board::from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let all_pieces = vec![PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+        assert_eq!(allowed_promotions(&board, Square(6, 0), Square(7, 0), White), all_pieces);
+        assert_eq!(allowed_promotions(&board, Square(6, 0), Square(7, 1), White), all_pieces);
+        assert_eq!(allowed_promotions(&board, Square(6, 0), Square(7, 2), White), Vec::new());
+        assert_eq!(allowed_promotions(&board, Square(0, 4), Square(1, 4), White), Vec::new());
+
+        let mut promoted = board.clone();
+        promoted.make_move(promoted.parse_move("a7b8n").unwrap());
+        assert_eq!(promoted.squares[7][1], Some(Piece { color: White, kind: PieceType::Knight }));
+        assert_eq!(promoted.move_history[0].promotion(), Some(PieceType::Knight));
+
+        let mut promoted = board.clone();
+        promoted.make_move(promoted.parse_move("a7a8").unwrap());
+        assert_eq!(promoted.squares[7][0], Some(Piece { color: White, kind: PieceType::Queen }));
+
+        let mut not_promoted = board.clone();
+        not_promoted.make_move(not_promoted.parse_move("e1e2n").unwrap());
+        assert_eq!(not_promoted.squares[1][4], Some(Piece { color: White, kind: PieceType::King }));
+        assert_eq!(not_promoted.move_history[0].promotion(), None);
+    }
+
+    #[test]
+    fn test_fifty_and_seventy_five_move_rules() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 80").unwrap();
+        assert_eq!(claimable_draw(&board), None);
+        board.make_move(board.parse_move("a1b1").unwrap());
+        assert_eq!(board.halfmove_clock, 99);
+        assert_eq!(claimable_draw(&board), None);
+        board.make_move(board.parse_move("e8d8").unwrap());
+        assert_eq!(claimable_draw(&board), Some(DrawReason::FiftyMoveRule));
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+
+        let mut pawn_move = board.clone();
+        pawn_move.make_move(pawn_move.parse_move("e2e3").unwrap());
+        assert_eq!(pawn_move.halfmove_clock, 0);
+        assert_eq!(claimable_draw(&pawn_move), None);
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 149 120").unwrap();
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+        let mut quiet_move = board.clone();
+        quiet_move.make_move(quiet_move.parse_move("a1b1").unwrap());
+        assert_eq!(game_result(&quiet_move), GameStatus::Draw(DrawReason::SeventyFiveMoveRule));
+        assert_eq!(claimable_draw(&quiet_move), None);
+
+        let board = Board::from_fen("3k4/8/3K4/8/8/8/8/R7 w - - 149 120").unwrap();
+        let mut mate = board.clone();
+        mate.make_move(mate.parse_move("a1a8").unwrap());
+        assert_eq!(game_result(&mate), GameStatus::Win(White, WinReason::Checkmate));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/r2NK3 b - - 149 120").unwrap();
+        let mut capture = board.clone();
+        capture.make_move(capture.parse_move("a1d1").unwrap());
+        assert_eq!(capture.halfmove_clock, 0);
+        assert_eq!(game_result(&capture), GameStatus::InProgress);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let dead = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1n2K3 w - - 0 1",
+            "1b2k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ];
+        for fen in dead {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(insufficient_material(&board), "{}", fen);
+            assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::InsufficientMaterial), "{}", fen);
+        }
+
+        let alive = [
+            "4k3/8/8/8/8/8/8/3BK1B1 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K1B1 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "1n2k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ];
+        for fen in alive {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(!insufficient_material(&board), "{}", fen);
+            assert_eq!(game_result(&board), GameStatus::InProgress, "{}", fen);
+        }
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1").unwrap();
+        board.make_move(board.parse_move("e1d2").unwrap());
+        assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::InsufficientMaterial));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert!(has_mating_material(&board, White));
+        assert!(!has_mating_material(&board, Black));
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        assert!(!has_mating_material(&board, White));
+    }
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for m in moves {
+            assert_eq!(game_result(board), GameStatus::InProgress);
+            board.make_move(board.parse_move(m).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_repetition_side_to_move() {
+        // the rook cycles through three squares, the king through two, so the same squares return with the other side to move
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let cycle = [
+            "a1a2", "e8d8", "a2a3", "d8e8", "a3a1", "e8d8",
+            "a1a2", "d8e8", "a2a3", "e8d8", "a3a1", "d8e8",
+        ];
+        play(&mut board, &cycle);
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+        play(&mut board, &cycle);
+        assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn test_repetition_castling_rights() {
+        let mut board = new_board();
+        play(&mut board, &["g1f3", "g8f6"]);
+        let (squares, with_castling) = (board.squares, board.hash());
+        play(&mut board, &["h1g1", "h8g8", "g1h1", "g8h8"]);
+        assert_eq!(board.squares, squares);
+        assert_ne!(board.hash(), with_castling);
+        play(&mut board, &["f3g1", "f6g8", "g1f3", "g8f6"]);
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+    }
+
+    #[test]
+    fn test_repetition_en_passant() {
+        let king_walk = ["e8e7", "e1f1", "e7e8", "f1e1"];
+
+        // after e4 the d4 pawn could take en passant, the same squares later on are a different position
+        let mut board = Board::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+        play(&mut board, &["e2e4"]);
+        assert_eq!(board.en_passant_target(), Some(Square(2, 4)));
+        play(&mut board, &king_walk);
+        assert_eq!(board.en_passant_target(), None);
+        play(&mut board, &king_walk);
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(game_result(&board), GameStatus::InProgress);
+
+        // without a pawn able to capture the en passant square does not change the position
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        play(&mut board, &["e2e4"]);
+        assert_eq!(board.en_passant_target(), None);
+        play(&mut board, &king_walk);
+        play(&mut board, &king_walk);
+        assert_eq!(game_result(&board), GameStatus::Draw(DrawReason::ThreefoldRepetition));
+
+        // a pinned pawn cannot take en passant either
+        let board = Board::from_fen("8/8/8/8/k2pP2R/8/8/4K3 b - e3 0 1").unwrap();
+        assert_eq!(board.en_passant_target(), None);
+        let board = Board::from_fen("8/8/8/8/3pP3/8/8/k3K3 b - e3 0 1").unwrap();
+        assert_eq!(board.en_passant_target(), Some(Square(2, 4)));
     }
 }
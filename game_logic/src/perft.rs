@@ -0,0 +1,118 @@
+use crate::bitboard::Position;
+use crate::board::Board;
+
+fn perft_position(position: &mut Position, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = position.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves.into_iter()
+        .map(|m| {
+            let undo = position.make_move(m);
+            let nodes = perft_position(position, depth - 1);
+            position.unmake_move(m, undo);
+            nodes
+        })
+        .sum()
+}
+
+// counts the leaf nodes of the legal move tree, the standard way of checking a move generator
+pub fn perft(board: &Board, depth: u32) -> u64 {
+    perft_position(&mut Position::from_board(board, board.color_to_play()), depth)
+}
+
+// perft split by the first move, handy for finding which subtree disagrees with a reference engine,
+// at depth 0 the tree is the root alone and there is no first move to split by
+pub fn perft_divide(board: &Board, depth: u32) -> Vec<(String, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let mut position = Position::from_board(board, board.color_to_play());
+    let mut result: Vec<(String, u64)> = position.legal_moves().into_iter()
+        .map(|m| {
+            let name = board.create_move(m.move_from(), m.move_to(), m.promotion).expect("Legal moves exist on the board").to_string();
+            let undo = position.make_move(m);
+            let nodes = perft_position(&mut position, depth - 1);
+            position.unmake_move(m, undo);
+            (name, nodes)
+        })
+        .collect();
+    result.sort();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{new_board, Board};
+    use crate::perft::{perft, perft_divide};
+
+    // reference numbers from https://www.chessprogramming.org/Perft_Results
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let board = Board::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&board, depth as u32 + 1), nodes, "{} depth {}", fen, depth + 1);
+        }
+    }
+
+    #[test]
+    fn test_perft_initial_position() {
+        let board = new_board();
+        assert_eq!(perft(&board, 0), 1);
+        assert_eq!(perft(&board, 1), 20);
+        assert_eq!(perft(&board, 2), 400);
+        assert_eq!(perft(&board, 3), 8902);
+        assert_eq!(perft(&board, 4), 197281);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        assert_perft("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn test_perft_position_3() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn test_perft_position_4() {
+        assert_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]);
+        // the same position mirrored with colours swapped
+        assert_perft("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn test_perft_position_6() {
+        assert_perft("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890]);
+    }
+
+    #[test]
+    fn test_perft_chess960() {
+        // https://www.chessprogramming.org/Chess960_Perft_Results
+        assert_perft("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189]);
+        assert_perft("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002]);
+        assert_perft("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471]);
+        assert_perft("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440]);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let divide = perft_divide(&new_board(), 2);
+        assert_eq!(divide.len(), 20);
+        assert!(divide.iter().all(|(_, nodes)| *nodes == 20));
+        assert_eq!(divide[0].0, "a2a3");
+        assert!(perft_divide(&new_board(), 0).is_empty());
+
+        let board = Board::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
+        let names: Vec<String> = perft_divide(&board, 1).into_iter().map(|(name, _)| name).collect();
+        assert!(["d7c8q", "d7c8r", "d7c8b", "d7c8n", "e1g1"].iter().all(|m| names.contains(&m.to_string())));
+    }
+}
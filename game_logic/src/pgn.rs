@@ -0,0 +1,574 @@
+use std::fmt;
+use crate::board::{new_board, Board, Color, FenError, GameStatus, Move, WinReason};
+use crate::board::Color::{Black, White};
+use crate::moves::{from_san, to_san, SanError};
+use crate::variant::GameVariant;
+
+const LINE_LENGTH: usize = 80;
+const CHESS960_TAG: &str = "Chess960";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PgnResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+    Unknown,
+}
+
+impl PgnResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PgnResult::WhiteWon => "1-0",
+            PgnResult::BlackWon => "0-1",
+            PgnResult::Draw => "1/2-1/2",
+            PgnResult::Unknown => "*",
+        }
+    }
+
+    fn parse(token: &str) -> Option<PgnResult> {
+        match token {
+            "1-0" => Some(PgnResult::WhiteWon),
+            "0-1" => Some(PgnResult::BlackWon),
+            "1/2-1/2" => Some(PgnResult::Draw),
+            "*" => Some(PgnResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub san: String,
+    pub chess_move: Move,
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: PgnResult,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn start_board(&self) -> Result<Board, PgnError> {
+        let mut board = match self.tag("FEN") {
+            None => new_board(),
+            Some(fen) => Board::from_fen(fen).map_err(PgnError::InvalidFen)?,
+        };
+        // the usual setup is also one of the Chess960 positions, only the tag tells them apart
+        if self.tag("Variant") == Some(CHESS960_TAG) {
+            board.variant = GameVariant::Chess960;
+        }
+        Ok(board)
+    }
+
+    pub fn end_board(&self) -> Result<Board, PgnError> {
+        let mut board = self.start_board()?;
+        for mv in &self.moves {
+            board.make_move(mv.chess_move);
+        }
+        Ok(board)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    InvalidTag(String),
+    UnterminatedComment,
+    UnbalancedVariation,
+    UnexpectedToken(String),
+    InvalidMove(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+    InvalidFen(FenError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair '{}'", tag),
+            PgnError::UnterminatedComment => write!(f, "comment is not terminated"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced variation parentheses"),
+            PgnError::UnexpectedToken(token) => write!(f, "unexpected token '{}'", token),
+            PgnError::InvalidMove(san) => write!(f, "cannot parse move '{}'", san),
+            PgnError::IllegalMove(san) => write!(f, "illegal move '{}'", san),
+            PgnError::AmbiguousMove(san) => write!(f, "ambiguous move '{}'", san),
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<SanError> for PgnError {
+    fn from(e: SanError) -> Self {
+        match e {
+            SanError::InvalidMove(san) => PgnError::InvalidMove(san),
+            SanError::IllegalMove(san) => PgnError::IllegalMove(san),
+            SanError::AmbiguousMove(san) => PgnError::AmbiguousMove(san),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(PgnResult),
+    San(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '%' if line_start => {
+                chars.by_ref().find(|&x| x == '\n');
+            }
+            '[' => {
+                let mut tag = String::new();
+                let mut in_quotes = false;
+                let mut escaped = false;
+                loop {
+                    match chars.next() {
+                        None => return Err(PgnError::InvalidTag(tag)),
+                        Some(']') if !in_quotes => break,
+                        Some(x) => {
+                            if in_quotes && !escaped && x == '\\' {
+                                escaped = true;
+                                continue;
+                            }
+                            if !escaped && x == '"' {
+                                in_quotes = !in_quotes;
+                            }
+                            escaped = false;
+                            tag.push(x);
+                        }
+                    }
+                }
+                tokens.push(parse_tag(&tag)?);
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(PgnError::UnterminatedComment),
+                        Some('}') => break,
+                        Some(x) => comment.push(x),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&x| x != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                let nag = digits.parse().map_err(|_| PgnError::UnexpectedToken(format!("${}", digits)))?;
+                tokens.push(Token::Nag(nag));
+            }
+            _ => {
+                let mut symbol = c.to_string();
+                while let Some(&x) = chars.peek().filter(|x| !x.is_whitespace() && !"{}()[];$".contains(**x)) {
+                    symbol.push(x);
+                    chars.next();
+                }
+                if let Some(result) = PgnResult::parse(&symbol) {
+                    tokens.push(Token::Result(result));
+                }
+                else {
+                    // move numbers like "12." or "12..." may be glued to the move itself
+                    let without_number = symbol.trim_start_matches(|x: char| x.is_ascii_digit());
+                    let san = if without_number.starts_with('.') { without_number.trim_start_matches('.') } else { symbol.as_str() };
+                    if !san.is_empty() {
+                        tokens.push(Token::San(san.to_string()));
+                    }
+                }
+            }
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+fn parse_tag(tag: &str) -> Result<Token, PgnError> {
+    let invalid = || PgnError::InvalidTag(tag.to_string());
+    let (name, value) = tag.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let value = value.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(invalid());
+    }
+    Ok(Token::Tag(name.to_string(), value[1..value.len() - 1].to_string()))
+}
+
+pub fn read_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let tokens = tokenize(text)?;
+    let mut pos = 0;
+    let mut games = Vec::new();
+    while pos < tokens.len() {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = tokens.get(pos) {
+            tags.push((name.clone(), value.clone()));
+            pos += 1;
+        }
+        let mut game = PgnGame { tags, comments: Vec::new(), moves: Vec::new(), result: PgnResult::Unknown };
+        let board = game.start_board()?;
+        let (moves, comments) = parse_line(&tokens, &mut pos, board, false)?;
+        game.moves = moves;
+        game.comments = comments;
+        if let Some(Token::Result(result)) = tokens.get(pos) {
+            game.result = *result;
+            pos += 1;
+        }
+        games.push(game);
+    }
+    Ok(games)
+}
+
+fn parse_line(tokens: &[Token], pos: &mut usize, mut board: Board, in_variation: bool) -> Result<(Vec<PgnMove>, Vec<String>), PgnError> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut comments = Vec::new();
+    let mut before_last_move: Option<Board> = None;
+    while let Some(token) = tokens.get(*pos) {
+        match token {
+            Token::San(symbol) => {
+                let (san, nags) = split_annotations(symbol);
+                let chess_move = from_san(&board, san)?;
+                let san = to_san(&board, &chess_move);
+                before_last_move = Some(board.clone());
+                board.make_move(chess_move);
+                moves.push(PgnMove { san, chess_move, nags, comments: Vec::new(), variations: Vec::new() });
+            }
+            Token::Nag(nag) => match moves.last_mut() {
+                None => return Err(PgnError::UnexpectedToken(format!("${}", nag))),
+                Some(mv) => mv.nags.push(*nag),
+            },
+            Token::Comment(comment) => match moves.last_mut() {
+                None => comments.push(comment.clone()),
+                Some(mv) => mv.comments.push(comment.clone()),
+            },
+            Token::Open => {
+                let start = before_last_move.clone().ok_or(PgnError::UnbalancedVariation)?;
+                *pos += 1;
+                let (variation, _) = parse_line(tokens, pos, start, true)?;
+                moves.last_mut().expect("Variation follows a move").variations.push(variation);
+            }
+            Token::Close => {
+                return if in_variation { Ok((moves, comments)) } else { Err(PgnError::UnbalancedVariation) };
+            }
+            Token::Result(_) | Token::Tag(_, _) => {
+                return if in_variation { Err(PgnError::UnbalancedVariation) } else { Ok((moves, comments)) };
+            }
+        }
+        *pos += 1;
+    }
+    if in_variation {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    Ok((moves, comments))
+}
+
+fn split_annotations(symbol: &str) -> (&str, Vec<u8>) {
+    let san = symbol.trim_end_matches(['!', '?']);
+    let nag = match &symbol[san.len()..] {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    };
+    (san, nag.into_iter().collect())
+}
+
+// the `Termination` tag values from the PGN standard
+fn termination(status: GameStatus) -> Option<&'static str> {
+    match status {
+        GameStatus::InProgress => None,
+        GameStatus::Win(_, WinReason::Timeout) => Some("time forfeit"),
+        GameStatus::Win(_, WinReason::Abandonment) => Some("abandoned"),
+        _ => Some("normal"),
+    }
+}
+
+// the result is whatever the variant's rules make of the final position
+pub fn pgn_from_board(board: &Board, tags: Vec<(String, String)>) -> PgnGame {
+    pgn_with_result(board, board.variant.rules().game_result(board), tags)
+}
+
+// for games which ended off the board, by resignation, timeout or agreement
+pub fn pgn_with_result(board: &Board, status: GameStatus, tags: Vec<(String, String)>) -> PgnGame {
+    let result = match status {
+        GameStatus::InProgress => PgnResult::Unknown,
+        GameStatus::Win(White, _) => PgnResult::WhiteWon,
+        GameStatus::Win(Black, _) => PgnResult::BlackWon,
+        GameStatus::Draw(_) => PgnResult::Draw,
+    };
+    let mut game = PgnGame { tags, comments: Vec::new(), moves: Vec::new(), result };
+    if game.tag("Result").is_none() {
+        game.tags.push(("Result".to_string(), result.as_str().to_string()));
+    }
+    if let Some(termination) = termination(status).filter(|_| game.tag("Termination").is_none()) {
+        game.tags.push(("Termination".to_string(), termination.to_string()));
+    }
+    // the board does not remember where it started, so the moves are taken back to find out
+    let mut replay = board.clone();
+    while replay.unmake_move().is_some() {}
+    let start_fen = replay.to_fen();
+    let chess960 = board.variant == GameVariant::Chess960;
+    if chess960 && game.tag("Variant").is_none() {
+        game.tags.push(("Variant".to_string(), CHESS960_TAG.to_string()));
+    }
+    if (chess960 || start_fen != new_board().to_fen()) && game.tag("FEN").is_none() {
+        game.tags.push(("SetUp".to_string(), "1".to_string()));
+        game.tags.push(("FEN".to_string(), start_fen));
+    }
+    for &chess_move in &board.move_history {
+        let san = to_san(&replay, &chess_move);
+        replay.make_move(chess_move);
+        game.moves.push(PgnMove { san, chess_move, nags: Vec::new(), comments: Vec::new(), variations: Vec::new() });
+    }
+    game
+}
+
+pub fn write_pgn(game: &PgnGame) -> String {
+    let mut result = String::new();
+    for (name, value) in &game.tags {
+        result.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    if !game.tags.is_empty() {
+        result.push('\n');
+    }
+
+    let (fullmove, color) = game.start_board().map_or((1, White), |b| (b.fullmove_number, b.side_to_move));
+    let mut words: Vec<String> = game.comments.iter().map(|c| format_comment(c)).collect();
+    write_line(&game.moves, fullmove, color, &mut words);
+    words.push(game.result.as_str().to_string());
+
+    let mut line = String::new();
+    for word in words.join(" ").split(' ') {
+        if !line.is_empty() && line.len() + 1 + word.len() > LINE_LENGTH {
+            result.push_str(&line);
+            result.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    result.push_str(&line);
+    result.push('\n');
+    result
+}
+
+fn format_comment(comment: &str) -> String {
+    format!("{{{}}}", comment.replace(['\n', '\r'], " ").replace('}', ""))
+}
+
+fn write_line(moves: &[PgnMove], mut fullmove: u32, mut color: Color, words: &mut Vec<String>) {
+    let mut need_number = true;
+    for mv in moves {
+        if color == White {
+            words.push(format!("{}.", fullmove));
+        }
+        else if need_number {
+            words.push(format!("{}...", fullmove));
+        }
+        words.push(mv.san.clone());
+        words.extend(mv.nags.iter().map(|nag| format!("${}", nag)));
+        words.extend(mv.comments.iter().map(|c| format_comment(c)));
+        need_number = !mv.comments.is_empty();
+        for variation in &mv.variations {
+            let mut inner = Vec::new();
+            write_line(variation, fullmove, color, &mut inner);
+            words.push(format!("({})", inner.join(" ")));
+            need_number = true;
+        }
+        if color == Black {
+            fullmove += 1;
+        }
+        color = color.opposite();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{new_board, new_chess960_board, to_string, Board, DrawReason, GameStatus, MoveKind, Piece, PieceType, Square, WinReason};
+    use crate::board::Color::{Black, White};
+    use crate::pgn::{pgn_from_board, pgn_with_result, read_pgn, write_pgn, PgnError, PgnResult};
+    use crate::variant::GameVariant;
+
+    const GAME: &str = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3 $1 Nc6 (2... d6 3. d4 {Philidor} (3. Bc4) 3... exd4) 3. Bb5 a6?!
+; rest of line comment
+4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 11. Nbd2 Bb7
+12. Bc2 Re8 13. Nf1 Bf8 14. Ng3 g6 15. a4 c5 16. d5 c4 17. Bg5 h6 18. Be3 Nc5
+19. Qd2 h5 20. Bg5 Be7 21. Bxf6 Bxf6 1-0
+"#;
+
+    #[test]
+    fn test_read_pgn() {
+        let games = read_pgn(GAME).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.tag("White"), Some("Alice"));
+        assert_eq!(game.result, PgnResult::WhiteWon);
+        assert_eq!(game.comments, vec!["Opening comment".to_string()]);
+        assert_eq!(game.moves.len(), 42);
+        assert_eq!(game.moves[2].nags, vec![1]);
+        assert_eq!(game.moves[3].variations.len(), 1);
+        let variation = &game.moves[3].variations[0];
+        assert_eq!(variation.iter().map(|m| m.san.as_str()).collect::<Vec<_>>(), vec!["d6", "d4", "exd4"]);
+        assert_eq!(variation[1].comments, vec!["Philidor".to_string()]);
+        assert_eq!(variation[1].variations[0][0].san, "Bc4");
+        assert_eq!(game.moves[5].nags, vec![6]);
+        assert_eq!(game.moves[5].comments, vec!["rest of line comment".to_string()]);
+        assert_eq!(game.moves[8].san, "O-O");
+        assert_eq!(game.moves[8].chess_move.to, Square(0, 6));
+        assert_eq!(game.moves[19].san, "Nbd7");
+        assert_eq!(game.moves[41].san, "Bxf6");
+    }
+
+    #[test]
+    fn test_write_pgn_round_trip() {
+        let game = read_pgn(GAME).unwrap().remove(0);
+        let written = write_pgn(&game);
+        assert!(written.lines().all(|line| line.len() <= 80));
+        assert!(written.split_whitespace().collect::<Vec<_>>().join(" ").contains("2. Nf3 $1 Nc6 (2... d6 3. d4 {Philidor} (3. Bc4) 3... exd4) 3. Bb5"));
+        let reread = read_pgn(&written).unwrap().remove(0);
+        assert_eq!(reread, game);
+    }
+
+    #[test]
+    fn test_pgn_from_board() {
+        let mut board = new_board();
+        board.make_move(board.parse_move("e2e4").unwrap());
+        board.make_move(board.parse_move("e7e5").unwrap());
+        board.make_move(board.parse_move("f1c4").unwrap());
+        board.make_move(board.parse_move("b8c6").unwrap());
+        board.make_move(board.parse_move("d1h5").unwrap());
+        board.make_move(board.parse_move("g8f6").unwrap());
+        board.make_move(board.parse_move("h5f7").unwrap());
+        let game = pgn_from_board(&board, vec![("White".to_string(), "Scholar".to_string())]);
+        assert_eq!(game.result, PgnResult::WhiteWon);
+        assert_eq!(write_pgn(&game), "[White \"Scholar\"]\n[Result \"1-0\"]\n[Termination \"normal\"]\n\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n");
+        let end = read_pgn(&write_pgn(&game)).unwrap()[0].end_board().unwrap();
+        assert_eq!(to_string(&end), to_string(&board));
+    }
+
+    #[test]
+    fn test_pgn_with_result() {
+        let mut board = new_board();
+        board.make_move(board.parse_move("e2e4").unwrap());
+        assert!(pgn_from_board(&board, Vec::new()).tag("Termination").is_none());
+
+        let game = pgn_with_result(&board, GameStatus::Win(White, WinReason::Timeout), Vec::new());
+        assert_eq!(game.result, PgnResult::WhiteWon);
+        assert_eq!(game.tag("Termination"), Some("time forfeit"));
+        let game = pgn_with_result(&board, GameStatus::Win(Black, WinReason::Resignation), Vec::new());
+        assert_eq!(write_pgn(&game), "[Result \"0-1\"]\n[Termination \"normal\"]\n\n1. e4 0-1\n");
+        let game = pgn_with_result(&board, GameStatus::Draw(DrawReason::Agreement), Vec::new());
+        assert_eq!(game.result, PgnResult::Draw);
+        let game = pgn_with_result(&board, GameStatus::Win(Black, WinReason::Abandonment), vec![("Termination".to_string(), "emergency".to_string())]);
+        assert_eq!(game.tag("Termination"), Some("emergency"));
+    }
+
+    #[test]
+    fn test_pgn_from_set_up_position() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        board.make_move(board.parse_move("a1a7").unwrap());
+        let game = pgn_from_board(&board, Vec::new());
+        assert_eq!(game.tag("SetUp"), Some("1"));
+        assert_eq!(game.tag("FEN"), Some("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"));
+        assert!(write_pgn(&game).ends_with("\n1. Ra7 *\n"));
+        let end = read_pgn(&write_pgn(&game)).unwrap()[0].end_board().unwrap();
+        assert_eq!(end.to_fen(), board.to_fen());
+    }
+
+    #[test]
+    fn test_pgn_from_chess960_board() {
+        let mut board = new_chess960_board(0).unwrap();
+        board.make_move(board.parse_move("d1c3").unwrap());
+        let game = pgn_from_board(&board, Vec::new());
+        assert_eq!(game.tag("Variant"), Some("Chess960"));
+        assert_eq!(game.tag("FEN"), Some("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"));
+        assert_eq!(game.moves[0].san, "Nc3");
+        let end = read_pgn(&write_pgn(&game)).unwrap()[0].end_board().unwrap();
+        assert_eq!(end.to_fen(), board.to_fen());
+        assert_eq!(end.variant, GameVariant::Chess960);
+
+        // the usual setup still needs the tags, or a reader would take it for standard chess
+        let mut board = new_chess960_board(518).unwrap();
+        board.make_move(board.parse_move("e2e4").unwrap());
+        let game = pgn_from_board(&board, Vec::new());
+        assert_eq!(game.tag("Variant"), Some("Chess960"));
+        assert_eq!(read_pgn(&write_pgn(&game)).unwrap()[0].start_board().unwrap().variant, GameVariant::Chess960);
+    }
+
+    #[test]
+    fn test_read_multiple_games_and_fen() {
+        let text = "[Event \"a\"]\n\n1. d4 d5 *\n\n[Event \"b\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 30\"]\n\n30... Kd7 31. O-O-O+ 1/2-1/2\n";
+        let games = read_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].result, PgnResult::Unknown);
+        assert_eq!(games[1].moves[1].san, "O-O-O+");
+        assert_eq!(games[1].result, PgnResult::Draw);
+        assert!(write_pgn(&games[1]).ends_with("30... Kd7 31. O-O-O+ 1/2-1/2\n"));
+    }
+
+    #[test]
+    fn test_underpromotion() {
+        let text = "[FEN \"4k3/1P6/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. b8=N Kf7 2. Nd7 *\n";
+        let game = read_pgn(text).unwrap().remove(0);
+        assert_eq!(game.moves[0].chess_move.promotion(), Some(PieceType::Knight));
+        let board = game.end_board().unwrap();
+        assert_eq!(board.squares[6][3], Some(Piece { color: White, kind: PieceType::Knight }));
+        assert_eq!(board.move_history[0].kind, MoveKind::Promotion(PieceType::Knight));
+        let exported = pgn_from_board(&board, vec![("FEN".to_string(), "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string())]);
+        assert_eq!(exported.moves, game.moves);
+    }
+
+    #[test]
+    fn test_read_pgn_errors() {
+        assert_eq!(read_pgn("1. e5"), Err(PgnError::IllegalMove("e5".to_string())));
+        assert_eq!(read_pgn("1. e4 {unfinished"), Err(PgnError::UnterminatedComment));
+        assert_eq!(read_pgn("1. e4 (1. d4"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(read_pgn("1. e4 )"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(read_pgn("[Event unquoted]"), Err(PgnError::InvalidTag("Event unquoted".to_string())));
+        assert_eq!(read_pgn("1. Zz9"), Err(PgnError::InvalidMove("Zz9".to_string())));
+        assert_eq!(read_pgn("1. d4 d5 2. Nf3 Nf6 3. Nd2"), Err(PgnError::AmbiguousMove("Nd2".to_string())));
+    }
+}
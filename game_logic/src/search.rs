@@ -0,0 +1,485 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::bitboard::{BitMove, Position};
+use crate::board::{Board, Color, Move, PieceType};
+use crate::eval::{evaluate_position, piece_value};
+
+pub const MATE_SCORE: i32 = 30_000;
+// anything above this is a forced mate, `MATE_SCORE - score` plies away
+const MATE_THRESHOLD: i32 = MATE_SCORE - 1_000;
+const INFINITY: i32 = MATE_SCORE + 1;
+pub const MAX_DEPTH: u32 = 64;
+const MAX_PLY: usize = 128;
+pub const DEFAULT_HASH_MB: usize = 16;
+// the clock and the stop flag are only looked at every so many nodes
+const CHECK_INTERVAL: u64 = 2048;
+// history scores stay below the killer moves, the whole table is halved when one goes past this
+const HISTORY_LIMIT: i32 = 70_000;
+
+// unset limits do not apply, with none set the search goes on to `MAX_DEPTH` or until stopped
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    // `None` only when the side to move has no legal move
+    pub best_move: Option<Move>,
+    // centipawns for the side to move, see `mate_in` for mate scores
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    pub elapsed: Duration,
+}
+
+impl SearchResult {
+    // moves until mate, negative when the side to move is the one getting mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_THRESHOLD {
+            return None;
+        }
+        let moves = (MATE_SCORE - self.score.abs() + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    // the score is at least this much (a beta cutoff)
+    Lower,
+    // the score is at most this much (no move raised alpha)
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TableEntry {
+    key: u64,
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best_move: Option<BitMove>,
+}
+
+// keeps the transposition table and move ordering statistics between searches, so a game is best played with one
+pub struct Searcher {
+    table: Vec<Option<TableEntry>>,
+    killers: [[Option<BitMove>; 2]; MAX_PLY],
+    history: [[[i32; 64]; 64]; 2],
+    pv: Vec<Vec<BitMove>>,
+    path: Vec<u64>,
+    game_positions: HashSet<u64>,
+    limits: SearchLimits,
+    nodes: u64,
+    start: Instant,
+    aborted: bool,
+    // ends the search early when set, whoever sets it clears it before the next search
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Searcher::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl Searcher {
+    pub fn new(hash_mb: usize) -> Searcher {
+        let mut searcher = Searcher {
+            table: Vec::new(),
+            killers: [[None; 2]; MAX_PLY],
+            history: [[[0; 64]; 64]; 2],
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            path: Vec::new(),
+            game_positions: HashSet::new(),
+            limits: SearchLimits::default(),
+            nodes: 0,
+            start: Instant::now(),
+            aborted: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        searcher.resize_table(hash_mb);
+        searcher
+    }
+
+    // the entry count is rounded down to a power of two, at least one entry is kept
+    pub fn resize_table(&mut self, hash_mb: usize) {
+        let entries = (hash_mb * 1024 * 1024 / size_of::<Option<TableEntry>>()).max(1);
+        self.table = vec![None; 1 << entries.ilog2()];
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(None);
+        self.history = [[[0; 64]; 64]; 2];
+    }
+
+    pub fn search(&mut self, board: &Board, limits: SearchLimits) -> SearchResult {
+        self.search_with(board, limits, |_| {})
+    }
+
+    // iterative deepening, `report` is called after every finished depth
+    pub fn search_with(&mut self, board: &Board, limits: SearchLimits, mut report: impl FnMut(&SearchResult)) -> SearchResult {
+        self.limits = limits;
+        self.nodes = 0;
+        self.start = Instant::now();
+        self.aborted = false;
+        self.killers = [[None; 2]; MAX_PLY];
+        // older history counts matter less
+        self.history.iter_mut().flatten().flatten().for_each(|score| *score /= 8);
+        self.game_positions = board.position_counter.keys().copied().collect();
+
+        let mut position = Position::from_board(board, board.color_to_play());
+        // a legal move to play even if the first iteration gets cut short
+        let fallback = position.legal_moves().first().map(|&m| to_moves(board, &[m])).unwrap_or_default();
+        let mut result = SearchResult { best_move: fallback.first().copied(), score: 0, depth: 0, nodes: 0, pv: fallback, elapsed: Duration::ZERO };
+
+        for depth in 1..=limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH) {
+            self.path.clear();
+            let score = self.negamax(&mut position, depth, 0, -INFINITY, INFINITY);
+            if self.aborted {
+                break;
+            }
+            let pv = to_moves(board, &self.pv[0]);
+            result = SearchResult { best_move: pv.first().copied(), score, depth, nodes: self.nodes, pv, elapsed: self.start.elapsed() };
+            report(&result);
+            let mate_found = score.abs() >= MATE_THRESHOLD && MATE_SCORE - score.abs() <= depth as i32;
+            // the next depth usually takes longer than all the previous ones together
+            let time_short = limits.time.is_some_and(|time| self.start.elapsed() * 2 > time);
+            if result.best_move.is_none() || mate_found || time_short {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result.elapsed = self.start.elapsed();
+        result
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted {
+            let out_of_nodes = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+            let out_of_time = self.nodes.is_multiple_of(CHECK_INTERVAL) &&
+                (self.stop.load(Ordering::Relaxed) || self.limits.time.is_some_and(|time| self.start.elapsed() >= time));
+            self.aborted = out_of_nodes || out_of_time;
+        }
+        self.aborted
+    }
+
+    fn negamax(&mut self, position: &mut Position, depth: u32, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let hash = position.hash();
+        if ply > 0 && (self.game_positions.contains(&hash) || self.path.contains(&hash)) {
+            return 0;
+        }
+        let in_check = position.in_check(position.side_to_move);
+        // look one move further when in check, so the search does not stop just before a mate
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+
+        let entry = self.probe(hash);
+        if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let score = score_from_table(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        let mut moves = position.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE_SCORE + ply as i32 } else { 0 };
+        }
+        self.order_moves(position, &mut moves, entry.and_then(|entry| entry.best_move), ply);
+
+        self.path.push(hash);
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for m in moves {
+            let quiet = !is_tactical(position, m);
+            let undo = position.make_move(m);
+            let score = -self.negamax(position, depth - 1, ply + 1, -beta, -alpha);
+            position.unmake_move(m, undo);
+            if self.aborted {
+                self.path.pop();
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
+            if score > alpha {
+                alpha = score;
+                let child = std::mem::take(&mut self.pv[ply + 1]);
+                self.pv[ply] = std::iter::once(m).chain(child.iter().copied()).collect();
+                self.pv[ply + 1] = child;
+            }
+            if alpha >= beta {
+                if quiet {
+                    let killers = &mut self.killers[ply];
+                    if killers[0] != Some(m) {
+                        killers[1] = killers[0];
+                        killers[0] = Some(m);
+                    }
+                    let score = &mut self.history[side_index(position.side_to_move)][m.from as usize][m.to as usize];
+                    *score += (depth * depth) as i32;
+                    if *score > HISTORY_LIMIT {
+                        self.history.iter_mut().flatten().flatten().for_each(|score| *score /= 2);
+                    }
+                }
+                break;
+            }
+        }
+        self.path.pop();
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.store(TableEntry { key: hash, depth, score: score_to_table(best_score, ply), bound, best_move });
+        best_score
+    }
+
+    // only captures and promotions, so the evaluation is never taken in the middle of an exchange
+    fn quiescence(&mut self, position: &mut Position, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let in_check = position.in_check(position.side_to_move);
+        let mut moves = position.legal_moves();
+        if in_check && moves.is_empty() {
+            return -MATE_SCORE + ply as i32;
+        }
+        if ply >= MAX_PLY - 1 {
+            return evaluate_position(position);
+        }
+        // standing pat is not an option when in check, every evasion is looked at instead
+        if !in_check {
+            let stand_pat = evaluate_position(position);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            moves.retain(|&m| is_tactical(position, m));
+        }
+        self.order_moves(position, &mut moves, None, ply);
+
+        for m in moves {
+            let undo = position.make_move(m);
+            let score = -self.quiescence(position, ply + 1, -beta, -alpha);
+            position.unmake_move(m, undo);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    // the table move first, then captures (most valuable victim, least valuable attacker), promotions, killers and history
+    fn order_moves(&self, position: &Position, moves: &mut [BitMove], table_move: Option<BitMove>, ply: usize) {
+        moves.sort_by_cached_key(|&m| {
+            let score = if Some(m) == table_move {
+                1_000_000
+            } else if let Some(victim) = captured_kind(position, m) {
+                let attacker = position.piece_at(m.from).map_or(0, |piece| piece_value(piece.kind));
+                100_000 + piece_value(victim) * 10 - attacker / 10
+            } else if let Some(kind) = m.promotion {
+                90_000 + piece_value(kind)
+            } else if self.killers[ply][0] == Some(m) {
+                80_000
+            } else if self.killers[ply][1] == Some(m) {
+                79_000
+            } else {
+                self.history[side_index(position.side_to_move)][m.from as usize][m.to as usize]
+            };
+            Reverse(score)
+        });
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        key as usize & (self.table.len() - 1)
+    }
+
+    fn probe(&self, key: u64) -> Option<TableEntry> {
+        self.table[self.slot(key)].filter(|entry| entry.key == key)
+    }
+
+    // a deeper search of another position is kept over a shallower one of this position
+    fn store(&mut self, entry: TableEntry) {
+        let index = self.slot(entry.key);
+        let slot = &mut self.table[index];
+        if slot.is_none_or(|old| old.key == entry.key || old.depth <= entry.depth) {
+            *slot = Some(entry);
+        }
+    }
+}
+
+fn side_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+// castling onto the own rook is not a capture, a pawn moving sideways onto an empty square takes en passant
+fn captured_kind(position: &Position, m: BitMove) -> Option<PieceType> {
+    let piece = position.piece_at(m.from)?;
+    match position.piece_at(m.to) {
+        Some(target) if target.color != piece.color => Some(target.kind),
+        None if piece.kind == PieceType::Pawn && m.from % 8 != m.to % 8 => Some(PieceType::Pawn),
+        _ => None,
+    }
+}
+
+fn is_tactical(position: &Position, m: BitMove) -> bool {
+    m.promotion.is_some() || captured_kind(position, m).is_some()
+}
+
+// mate scores are stored relative to the position, not to the root
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    match score {
+        s if s >= MATE_THRESHOLD => s + ply as i32,
+        s if s <= -MATE_THRESHOLD => s - ply as i32,
+        s => s,
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    match score {
+        s if s >= MATE_THRESHOLD => s - ply as i32,
+        s if s <= -MATE_THRESHOLD => s + ply as i32,
+        s => s,
+    }
+}
+
+// replays the moves on a copy of the board to describe them as `Move`s
+fn to_moves(board: &Board, moves: &[BitMove]) -> Vec<Move> {
+    let mut board = board.clone();
+    let mut result = Vec::new();
+    for m in moves {
+        let Some(chess_move) = board.create_move(m.move_from(), m.move_to(), m.promotion) else {
+            break;
+        };
+        board.make_move(chess_move);
+        result.push(chess_move);
+    }
+    result
+}
+
+// a one-off search with a fresh table
+pub fn best_move(board: &Board, limits: SearchLimits) -> SearchResult {
+    Searcher::new(1).search(board, limits)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::board::{new_board, Board};
+    use std::sync::atomic::Ordering;
+    use crate::search::{best_move, SearchLimits, Searcher, MAX_DEPTH};
+
+    fn search_fen(fen: &str, depth: u32) -> (String, Option<i32>) {
+        let board = Board::from_fen(fen).unwrap();
+        let result = best_move(&board, SearchLimits { depth: Some(depth), ..Default::default() });
+        (result.best_move.unwrap().to_string(), result.mate_in())
+    }
+
+    #[test]
+    fn test_finds_mate() {
+        // back rank mate
+        assert_eq!(search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2), ("a1a8".to_string(), Some(1)));
+        // scholar's mate
+        let (best, mate) = search_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", 3);
+        assert_eq!(best, "h5f7");
+        assert_eq!(mate, Some(1));
+        assert_eq!(search_fen("6k1/6p1/6K1/8/8/8/8/R6R w - - 0 1", 3), ("a1a8".to_string(), Some(1)));
+        // the side getting mated knows it
+        let board = Board::from_fen("7k/R7/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        let result = best_move(&board, SearchLimits { depth: Some(3), ..Default::default() });
+        assert_eq!(result.mate_in(), Some(-1));
+        assert_eq!(result.pv.len(), 2);
+    }
+
+    #[test]
+    fn test_wins_material() {
+        assert_eq!(search_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", 3).0, "d1d5");
+        // the knight on e5 is defended, taking it with the queen loses her
+        let (best, _) = search_fen("4k3/8/3p4/4n3/8/8/4Q3/4K3 w - - 0 1", 3);
+        assert_ne!(best, "e2e5");
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        let stalemate = Board::from_fen("k7/8/1Q6/8/8/8/8/4K3 b - - 0 1").unwrap();
+        let result = best_move(&stalemate, SearchLimits { depth: Some(3), ..Default::default() });
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn test_limits_and_pv() {
+        let mut searcher = Searcher::new(1);
+        let mut reported = Vec::new();
+        let result = searcher.search_with(&new_board(), SearchLimits { depth: Some(4), ..Default::default() }, |info| reported.push(info.depth));
+        assert_eq!(reported, vec![1, 2, 3, 4]);
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.best_move, result.pv.first().copied());
+        let mut board = new_board();
+        for m in &result.pv {
+            board.try_make_move(*m).unwrap();
+        }
+
+        let result = searcher.search(&new_board(), SearchLimits { nodes: Some(5000), ..Default::default() });
+        assert!(result.nodes <= 5000);
+        assert!(result.best_move.is_some());
+
+        let result = searcher.search(&new_board(), SearchLimits { time: Some(Duration::from_millis(50)), ..Default::default() });
+        assert!(result.depth < MAX_DEPTH);
+        assert!(result.best_move.is_some());
+
+        // with no limits only the stop flag ends the search, and it still has a move to give
+        searcher.stop.store(true, Ordering::Relaxed);
+        let result = searcher.search(&new_board(), SearchLimits::default());
+        assert!(result.depth < MAX_DEPTH);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_repetition_is_a_draw() {
+        // black's only move is Ka7, which is lost, unless that position was already on the board
+        let board = Board::from_fen("k7/2K5/8/8/8/8/8/7R b - - 0 1").unwrap();
+        assert!(best_move(&board, SearchLimits { depth: Some(3), ..Default::default() }).score < -400);
+        let mut repeated = board.clone();
+        for m in ["a8a7", "h1h2", "a7a8", "h2h1"] {
+            repeated.make_move(repeated.parse_move(m).unwrap());
+        }
+        assert_eq!(repeated.to_fen().split(' ').next(), board.to_fen().split(' ').next());
+        assert_eq!(best_move(&repeated, SearchLimits { depth: Some(3), ..Default::default() }).score, 0);
+    }
+}
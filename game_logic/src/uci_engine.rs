@@ -0,0 +1,227 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+use crate::board::{new_board, Board, CastlingRights, Move};
+use crate::variant::GameVariant;
+
+pub const MAX_SKILL: u32 = 20;
+pub const DEFAULT_MOVETIME_MS: u64 = 1000;
+const MIN_MOVETIME_MS: u64 = 50;
+const MAX_MOVETIME_MS: u64 = 10_000;
+// how long an engine may take to start up, and to answer once its time for a move is up
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const MOVE_MARGIN: Duration = Duration::from_secs(2);
+
+// how strong the engine opponent of a room plays, missing values fall back to full strength and a second a move
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub struct EngineSettings {
+    #[serde(default)]
+    pub skill: Option<u32>,
+    // milliseconds per move
+    #[serde(default)]
+    pub movetime: Option<u64>,
+}
+
+impl EngineSettings {
+    pub fn skill(&self) -> u32 {
+        self.skill.unwrap_or(MAX_SKILL).min(MAX_SKILL)
+    }
+
+    pub fn movetime(&self) -> Duration {
+        Duration::from_millis(self.movetime.unwrap_or(DEFAULT_MOVETIME_MS).clamp(MIN_MOVETIME_MS, MAX_MOVETIME_MS))
+    }
+}
+
+// UCI engines know standard chess and Chess960, any other variant would be played by the wrong rules
+pub fn plays_variant(variant: GameVariant) -> bool {
+    matches!(variant, GameVariant::Standard | GameVariant::Chess960)
+}
+
+// GUIs in Chess960 mode expect castling as the king taking its own rook, even from the standard setup,
+// everyone else expects the king's two-square step
+pub fn uci_move(m: &Move, rights: CastlingRights, chess960: bool) -> String {
+    match rights.castling_rook(m.kind, m.from.row()) {
+        Some((rook_from, _)) if chess960 => format!("{}{}", m.from, rook_from),
+        Some(_) => format!("{}{}", m.from, m.destination()),
+        None => m.to_string(),
+    }
+}
+
+// the game so far as a UCI `position` command, sent as moves from the start so the engine sees repetitions
+pub fn position_command(board: &Board) -> String {
+    let mut start = board.clone();
+    while start.unmake_move().is_some() {}
+    let start_fen = start.to_fen();
+    let position = if start_fen == new_board().to_fen() { "startpos".to_string() } else { format!("fen {}", start_fen) };
+
+    let chess960 = board.variant == GameVariant::Chess960;
+    let moves: Vec<String> = board.move_history.iter().map(|m| uci_move(m, board.castling_rights, chess960)).collect();
+    if moves.is_empty() {
+        format!("position {}", position)
+    } else {
+        format!("position {} moves {}", position, moves.join(" "))
+    }
+}
+
+// `None` when the engine has no move to play ("bestmove 0000" or "bestmove (none)") or sent something unreadable
+pub fn parse_best_move(board: &Board, line: &str) -> Option<Move> {
+    let mut tokens = line.split_whitespace();
+    match (tokens.next(), tokens.next()) {
+        (Some("bestmove"), Some(text)) => board.parse_move(text),
+        _ => None,
+    }
+}
+
+// a chess engine running as a child process, spoken to over its stdin and stdout
+pub struct UciEngine {
+    process: Child,
+    input: ChildStdin,
+    // lines read from the engine's stdout on a thread of their own, so waiting for them can time out
+    output: Receiver<String>,
+}
+
+impl UciEngine {
+    // starts the engine and waits until it is ready to search, engines without a "Skill Level" option play at full strength
+    pub fn start(path: &Path, skill: u32, chess960: bool) -> io::Result<UciEngine> {
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = process.stdin.take().expect("Stdin is piped");
+        let stdout = BufReader::new(process.stdout.take().expect("Stdout is piped"));
+        let (lines, output) = channel();
+        // ends when the engine closes its output or the engine is dropped
+        spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = UciEngine { process, input, output };
+
+        let timed_out = || io::Error::new(ErrorKind::TimedOut, "Engine did not get ready in time");
+        engine.send("uci")?;
+        engine.read_until("uciok", STARTUP_TIMEOUT)?.ok_or_else(timed_out)?;
+        engine.send(&format!("setoption name Skill Level value {}", skill))?;
+        if chess960 {
+            engine.send("setoption name UCI_Chess960 value true")?;
+        }
+        engine.send("isready")?;
+        engine.read_until("readyok", STARTUP_TIMEOUT)?.ok_or_else(timed_out)?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        log::debug!("Engine <- {}", command);
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()
+    }
+
+    // skips lines (info, id, option...) until one starting with `prefix` comes, `None` if none came in time
+    fn read_until(&mut self, prefix: &str, timeout: Duration) -> io::Result<Option<String>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.output.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) if line.starts_with(prefix) => {
+                    log::debug!("Engine -> {}", line);
+                    return Ok(Some(line));
+                }
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "Engine closed its output")),
+            }
+        }
+    }
+
+    // the move is not checked for legality, `None` as well when the engine is still thinking well after `movetime`
+    pub fn best_move(&mut self, board: &Board, movetime: Duration) -> io::Result<Option<Move>> {
+        self.send(&position_command(board))?;
+        self.send(&format!("go movetime {}", movetime.as_millis()))?;
+        let line = self.read_until("bestmove", movetime + MOVE_MARGIN)?;
+        Ok(line.and_then(|line| parse_best_move(board, &line)))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        // the engine has nothing to save, so one which ignores "quit" is not waited for
+        let _ = self.send("quit");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::board::{new_board, new_chess960_board, Board};
+    use crate::variant::GameVariant;
+    use crate::uci_engine::{parse_best_move, plays_variant, position_command, EngineSettings, UciEngine, MAX_SKILL};
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for m in moves {
+            board.make_move(board.parse_move(m).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_position_command() {
+        let mut board = new_board();
+        assert_eq!(position_command(&board), "position startpos");
+        play(&mut board, &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"]);
+        assert_eq!(position_command(&board), "position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1");
+
+        // castling written as the king taking its rook still goes out as the two-square step
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play(&mut board, &["e1h1", "e8c8"]);
+        assert_eq!(position_command(&board), "position fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1 e8c8");
+
+        let mut board = new_chess960_board(518).unwrap();
+        board.variant = GameVariant::Chess960;
+        play(&mut board, &["g1f3", "g8f6", "e2e4", "e7e5", "f1e2", "f8e7", "e1g1"]);
+        assert!(position_command(&board).ends_with("f8e7 e1h1"));
+    }
+
+    #[test]
+    fn test_parse_best_move() {
+        let board = new_board();
+        assert_eq!(parse_best_move(&board, "bestmove e2e4 ponder e7e5\n"), board.parse_move("e2e4"));
+        assert_eq!(parse_best_move(&board, "bestmove g1f3"), board.parse_move("g1f3"));
+        assert_eq!(parse_best_move(&board, "bestmove 0000"), None);
+        assert_eq!(parse_best_move(&board, "bestmove (none)"), None);
+        assert_eq!(parse_best_move(&board, "info depth 1"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_silent_engine_times_out() {
+        use std::os::unix::fs::PermissionsExt;
+        // gets ready like any engine, then never answers "go"
+        let path = std::env::temp_dir().join(format!("silent_engine_{}", std::process::id()));
+        std::fs::write(&path, "#!/bin/sh\nwhile read line; do\n  case \"$line\" in\n    uci) echo uciok;;\n    isready) echo readyok;;\n  esac\ndone\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut engine = UciEngine::start(&path, MAX_SKILL, false).unwrap();
+        assert_eq!(engine.best_move(&new_board(), Duration::from_millis(50)).unwrap(), None);
+        drop(engine);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_engine_settings() {
+        let settings: EngineSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.skill(), MAX_SKILL);
+        assert_eq!(settings.movetime(), Duration::from_secs(1));
+
+        let settings: EngineSettings = serde_json::from_str(r#"{"skill":99,"movetime":0}"#).unwrap();
+        assert_eq!(settings.skill(), MAX_SKILL);
+        assert_eq!(settings.movetime(), Duration::from_millis(50));
+
+        assert!(plays_variant(GameVariant::Chess960));
+        assert!(!plays_variant(GameVariant::ThreeCheck));
+    }
+}
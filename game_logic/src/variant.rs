@@ -0,0 +1,189 @@
+use rand::random;
+use crate::board::{new_board, new_chess960_board, Board, Color, DrawReason, GameStatus, PieceType, Square, WinReason, CHESS960_POSITIONS};
+use crate::moves;
+
+// the rules a room is played with, clients which do not send one get standard chess
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum GameVariant {
+    #[default]
+    Standard,
+    Chess960,
+    KingOfTheHill,
+    ThreeCheck,
+}
+
+impl GameVariant {
+    pub fn rules(&self) -> &'static dyn Variant {
+        match self {
+            GameVariant::Standard => &Standard,
+            GameVariant::Chess960 => &Chess960,
+            GameVariant::KingOfTheHill => &KingOfTheHill,
+            GameVariant::ThreeCheck => &ThreeCheck,
+        }
+    }
+}
+
+// every hook defaults to standard chess, a variant only overrides what it changes
+pub trait Variant {
+    fn start_position(&self) -> Board {
+        new_board()
+    }
+
+    // called after every move
+    fn game_result(&self, board: &Board) -> GameStatus {
+        moves::game_result(board)
+    }
+}
+
+pub struct Standard;
+
+impl Variant for Standard {}
+
+pub struct Chess960;
+
+impl Variant for Chess960 {
+    fn start_position(&self) -> Board {
+        new_chess960_board(random::<usize>() % CHESS960_POSITIONS).expect("Index is within the Chess960 positions")
+    }
+}
+
+// d4, e4, d5 and e5
+pub const HILL: [Square; 4] = [Square(3, 3), Square(3, 4), Square(4, 3), Square(4, 4)];
+
+// a king reaching the centre wins
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn start_position(&self) -> Board {
+        let mut board = new_board();
+        board.variant = GameVariant::KingOfTheHill;
+        board
+    }
+
+    fn game_result(&self, board: &Board) -> GameStatus {
+        for color in [Color::White, Color::Black] {
+            if board.king_positions.get(&color).is_some_and(|king| HILL.contains(king)) {
+                return GameStatus::Win(color, WinReason::KingOfTheHill);
+            }
+        }
+        // a lone king can still walk to the hill
+        match moves::game_result(board) {
+            GameStatus::Draw(DrawReason::InsufficientMaterial) => GameStatus::InProgress,
+            result => result,
+        }
+    }
+}
+
+pub const CHECKS_TO_WIN: u32 = 3;
+
+// giving the third check wins
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn start_position(&self) -> Board {
+        let mut board = new_board();
+        board.variant = GameVariant::ThreeCheck;
+        board
+    }
+
+    fn game_result(&self, board: &Board) -> GameStatus {
+        for color in [Color::White, Color::Black] {
+            if board.checks.given(color) >= CHECKS_TO_WIN {
+                return GameStatus::Win(color, WinReason::ThreeCheck);
+            }
+        }
+        // any piece besides the kings can still give checks
+        match moves::game_result(board) {
+            GameStatus::Draw(DrawReason::InsufficientMaterial) if !only_kings(board) => GameStatus::InProgress,
+            result => result,
+        }
+    }
+}
+
+fn only_kings(board: &Board) -> bool {
+    board.squares.iter().flatten().flatten().all(|piece| piece.kind == PieceType::King)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{new_board, Board, Color, FenError, GameStatus, Square, WinReason};
+    use crate::moves::allowed_moves;
+    use crate::variant::GameVariant;
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for m in moves {
+            board.make_move(board.parse_move(m).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_king_of_the_hill() {
+        let rules = GameVariant::KingOfTheHill.rules();
+        let mut board = Board::from_fen("4k3/8/8/8/8/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(rules.game_result(&board), GameStatus::InProgress);
+        // standard chess calls the same position a draw
+        assert_ne!(GameVariant::Standard.rules().game_result(&board), GameStatus::InProgress);
+        play(&mut board, &["e3d4"]);
+        assert_eq!(rules.game_result(&board), GameStatus::Win(Color::White, WinReason::KingOfTheHill));
+
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(rules.game_result(&board), GameStatus::Win(Color::Black, WinReason::KingOfTheHill));
+
+        // the king still cannot step onto an attacked hill square
+        let board = Board::from_fen("4k3/8/8/8/2r5/4K3/8/8 w - - 0 1").unwrap();
+        let targets: Vec<Square> = allowed_moves(&board, Square(2, 4), Color::White).iter().map(|m| m.to).collect();
+        assert!(!targets.contains(&Square(3, 3)));
+        assert!(!targets.contains(&Square(3, 4)));
+    }
+
+    #[test]
+    fn test_three_check() {
+        let rules = GameVariant::ThreeCheck.rules();
+        let mut board = rules.start_position();
+        play(&mut board, &["e2e4", "e7e5", "f1c4", "d7d6", "c4f7", "e8f7", "d1h5", "g7g6", "h5g6", "h7g6"]);
+        assert_eq!(board.checks.given(Color::White), 3);
+        assert_eq!(board.checks.given(Color::Black), 0);
+        assert_eq!(rules.game_result(&board), GameStatus::Win(Color::White, WinReason::ThreeCheck));
+        assert_eq!(GameVariant::Standard.rules().game_result(&board), GameStatus::InProgress);
+
+        board.unmake_move();
+        board.unmake_move();
+        assert_eq!(board.checks.given(Color::White), 2);
+        assert_eq!(rules.game_result(&board), GameStatus::InProgress);
+        assert_eq!(board.to_fen(), "rnbq1bnr/ppp2k1p/3p2p1/4p2Q/4P3/8/PPPP1PPP/RNB1K1NR w KQ - 0 5 +2+0");
+
+        // checks given before the position was set up still count
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0").unwrap();
+        assert_eq!(board.variant, GameVariant::ThreeCheck);
+        play(&mut board, &["a1a8"]);
+        assert_eq!(rules.game_result(&board), GameStatus::Win(Color::White, WinReason::ThreeCheck));
+
+        // a knight can still give checks, bare kings cannot
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4KN2 w - - 0 1").unwrap();
+        assert_eq!(rules.game_result(&board), GameStatus::InProgress);
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_ne!(rules.game_result(&board), GameStatus::InProgress);
+    }
+
+    #[test]
+    fn test_check_count_in_hash() {
+        // the same moves reach the same squares, but only one side has given a check on the way
+        let mut board = GameVariant::ThreeCheck.rules().start_position();
+        play(&mut board, &["e2e4", "d7d5", "f1b5", "c7c6", "b5f1"]);
+        let mut other = GameVariant::ThreeCheck.rules().start_position();
+        play(&mut other, &["e2e4", "d7d5", "g1f3", "c7c6", "f3g1"]);
+        assert_eq!(board.squares, other.squares);
+        assert_ne!(board.hash(), other.hash());
+        assert_eq!(board.hash(), Board::from_fen(&board.to_fen()).unwrap().hash());
+        assert_eq!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1 +4+0").err(), Some(FenError::InvalidChecks("+4+0".to_string())));
+    }
+
+    #[test]
+    fn test_start_position() {
+        assert_eq!(GameVariant::Standard.rules().start_position().to_fen(), new_board().to_fen());
+        assert_eq!(GameVariant::ThreeCheck.rules().start_position().squares, new_board().squares);
+        assert_eq!(GameVariant::ThreeCheck.rules().start_position().variant, GameVariant::ThreeCheck);
+        assert_eq!(GameVariant::KingOfTheHill.rules().start_position().variant, GameVariant::KingOfTheHill);
+        assert_eq!(GameVariant::Chess960.rules().start_position().variant, GameVariant::Chess960);
+    }
+}
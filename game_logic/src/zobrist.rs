@@ -0,0 +1,180 @@
+use crate::board::{Board, CastlingRights, Checks, Color, Piece, PieceType, Square, HEIGHT, WIDTH};
+use crate::variant::CHECKS_TO_WIN;
+
+// the keys are generated at compile time from a fixed seed, so hashes are the same across runs and builds
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+struct Keys {
+    pieces: [[u64; WIDTH * HEIGHT]; 12],
+    black_to_move: u64,
+    castling: [u64; 4],
+    en_passant: [u64; WIDTH],
+    // one key per side and number of checks given, from one to three
+    checks: [[u64; CHECKS_TO_WIN as usize]; 2],
+}
+
+const KEYS: Keys = generate_keys();
+
+// splitmix64
+const fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn generate_keys() -> Keys {
+    let mut state = SEED;
+    let mut pieces = [[0; WIDTH * HEIGHT]; 12];
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < WIDTH * HEIGHT {
+            pieces[piece][square] = next_random(&mut state);
+            square += 1;
+        }
+        piece += 1;
+    }
+    let black_to_move = next_random(&mut state);
+    let mut castling = [0; 4];
+    let mut i = 0;
+    while i < castling.len() {
+        castling[i] = next_random(&mut state);
+        i += 1;
+    }
+    let mut en_passant = [0; WIDTH];
+    let mut i = 0;
+    while i < WIDTH {
+        en_passant[i] = next_random(&mut state);
+        i += 1;
+    }
+    let mut checks = [[0; CHECKS_TO_WIN as usize]; 2];
+    let mut color = 0;
+    while color < 2 {
+        let mut i = 0;
+        while i < CHECKS_TO_WIN as usize {
+            checks[color][i] = next_random(&mut state);
+            i += 1;
+        }
+        color += 1;
+    }
+    Keys { pieces, black_to_move, castling, en_passant, checks }
+}
+
+fn piece_index(piece: Piece) -> usize {
+    let kind = match piece.kind {
+        PieceType::King => 0,
+        PieceType::Queen => 1,
+        PieceType::Rook => 2,
+        PieceType::Bishop => 3,
+        PieceType::Knight => 4,
+        PieceType::Pawn => 5,
+    };
+    if piece.color == Color::White { kind } else { kind + 6 }
+}
+
+pub fn piece_key(piece: Piece, square: Square) -> u64 {
+    KEYS.pieces[piece_index(piece)][square.0 * WIDTH + square.1]
+}
+
+pub fn side_to_move_key(color: Color) -> u64 {
+    if color == Color::Black { KEYS.black_to_move } else { 0 }
+}
+
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    [rights.white_king_side, rights.white_queen_side, rights.black_king_side, rights.black_queen_side].iter()
+        .zip(KEYS.castling)
+        .filter(|(&right, _)| right)
+        .fold(0, |hash, (_, key)| hash ^ key)
+}
+
+// only the file matters, the rank follows from the side to move
+pub fn en_passant_key(square: Option<Square>) -> u64 {
+    square.map_or(0, |square| KEYS.en_passant[square.col()])
+}
+
+// only Three-check games count checks, so every other game keeps the plain hash
+pub fn checks_key(checks: Checks) -> u64 {
+    [checks.white, checks.black].iter()
+        .zip(KEYS.checks)
+        .filter(|(&count, _)| count > 0)
+        .fold(0, |hash, (&count, keys)| hash ^ keys[count.min(CHECKS_TO_WIN) as usize - 1])
+}
+
+// computes the hash from scratch, `Board::make_move` keeps it up to date incrementally
+pub fn hash_position(board: &Board) -> u64 {
+    let mut hash = side_to_move_key(board.side_to_move)
+        ^ castling_key(board.castling_rights)
+        ^ en_passant_key(board.en_passant_target())
+        ^ checks_key(board.checks);
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            if let Some(piece) = board.squares[row][col] {
+                hash ^= piece_key(piece, Square(row, col));
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::Position;
+    use crate::board::{new_board, Board, STARTING_FEN};
+    use crate::zobrist::hash_position;
+
+    #[test]
+    fn test_incremental_hash() {
+        // en passant, underpromotion and castling on both sides of the board
+        let moves = [
+            "e2e4", "d7d5", "e4d5", "c7c5", "d5c6", "g8f6", "c6b7", "e7e6",
+            "b7a8n", "f8c5", "g1f3", "e8g8", "f1e2", "b8c6", "e1g1",
+        ];
+        let mut board = new_board();
+        for m in moves {
+            board.make_move(board.parse_move(m).unwrap());
+            assert_eq!(board.hash(), hash_position(&board), "{}", board.to_fen());
+            assert_eq!(board.hash(), Position::from_board(&board, board.color_to_play()).hash(), "{}", board.to_fen());
+            assert_eq!(board.hash(), Board::from_fen(&board.to_fen()).unwrap().hash(), "{}", board.to_fen());
+        }
+        assert_eq!(board.to_fen(), "N1bq1rk1/p4ppp/2n1pn2/2b5/8/5N2/PPPPBPPP/RNBQ1RK1 b - - 6 8");
+    }
+
+    #[test]
+    fn test_position_hash_follows_moves() {
+        // after e2e4 the black pawn on d4 could reach e3, but it is pinned to its king
+        for fen in ["8/8/8/8/k2p3R/8/4P3/4K3 w - - 0 1", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"] {
+            let board = Board::from_fen(fen).unwrap();
+            let mut position = Position::from_board(&board, board.color_to_play());
+            for m in position.legal_moves() {
+                let undo = position.make_move(m);
+                let mut after = board.clone();
+                after.make_move(board.create_move(m.move_from(), m.move_to(), m.promotion).unwrap());
+                assert_eq!(position.hash(), after.hash(), "{} {:?}", fen, m);
+                position.unmake_move(m, undo);
+                assert_eq!(position.hash(), board.hash());
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_transposition() {
+        let mut first = new_board();
+        let mut second = new_board();
+        for m in ["g1f3", "g8f6", "b1c3"] {
+            first.make_move(first.parse_move(m).unwrap());
+        }
+        for m in ["b1c3", "g8f6", "g1f3"] {
+            second.make_move(second.parse_move(m).unwrap());
+        }
+        assert_eq!(first.hash(), second.hash());
+        assert_ne!(first.hash(), new_board().hash());
+
+        // same squares with the other side to move
+        let flipped = Board::from_fen(&first.to_fen().replace(" b ", " w ")).unwrap();
+        assert_eq!(flipped.squares, first.squares);
+        assert_ne!(flipped.hash(), first.hash());
+        assert_eq!(new_board().hash(), Board::from_fen(STARTING_FEN).unwrap().hash());
+    }
+}